@@ -0,0 +1,154 @@
+/* Compares the arena-backed `RBTree` with the previous `Rc<RefCell<...>>` node
+ * representation and with `std::collections::BTreeSet`.
+ *
+ * Run with `cargo bench --bench storage [-- <n>]`; `n` defaults to 200000. Each
+ * structure gets the same shuffled keys to insert, an equal mix of present and
+ * missing keys to look up, and then every key in a fresh shuffle to remove. */
+#![allow(clippy::needless_return)]
+
+mod rc_tree;
+
+use std::collections::BTreeSet;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::SeedableRng;
+use rb_tree::RBTree;
+
+use rc_tree::RcTree;
+
+trait BenchSet {
+    fn new() -> Self;
+    fn insert(&mut self, key: u64);
+    fn contains(&self, key: &u64) -> bool;
+    fn remove(&mut self, key: &u64);
+    fn len(&self) -> usize;
+}
+
+impl BenchSet for RBTree<u64> {
+    fn new() -> Self {
+        RBTree::new()
+    }
+
+    fn insert(&mut self, key: u64) {
+        RBTree::insert(self, key);
+    }
+
+    fn contains(&self, key: &u64) -> bool {
+        return RBTree::contains(self, key);
+    }
+
+    fn remove(&mut self, key: &u64) {
+        RBTree::remove(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return RBTree::len(self);
+    }
+}
+
+impl BenchSet for RcTree<u64> {
+    fn new() -> Self {
+        RcTree::new()
+    }
+
+    fn insert(&mut self, key: u64) {
+        RcTree::insert(self, key);
+    }
+
+    fn contains(&self, key: &u64) -> bool {
+        return RcTree::contains(self, key);
+    }
+
+    fn remove(&mut self, key: &u64) {
+        RcTree::remove(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return RcTree::len(self);
+    }
+}
+
+impl BenchSet for BTreeSet<u64> {
+    fn new() -> Self {
+        BTreeSet::new()
+    }
+
+    fn insert(&mut self, key: u64) {
+        BTreeSet::insert(self, key);
+    }
+
+    fn contains(&self, key: &u64) -> bool {
+        return BTreeSet::contains(self, key);
+    }
+
+    fn remove(&mut self, key: &u64) {
+        BTreeSet::remove(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return BTreeSet::len(self);
+    }
+}
+
+struct Timings {
+    insert: Duration,
+    lookup: Duration,
+    remove: Duration
+}
+
+fn run<T: BenchSet>(inserts: &[u64], lookups: &[u64], removes: &[u64]) -> Timings {
+    let mut set = T::new();
+    let start = Instant::now();
+    for &key in inserts {
+        set.insert(key);
+    }
+    let insert = start.elapsed();
+    assert_eq!(set.len(), inserts.len());
+
+    let start = Instant::now();
+    let mut found = 0;
+    for key in lookups {
+        found += set.contains(black_box(key)) as usize;
+    }
+    let lookup = start.elapsed();
+    assert_eq!(found, lookups.len() / 2);
+
+    let start = Instant::now();
+    for key in removes {
+        set.remove(black_box(key));
+    }
+    let remove = start.elapsed();
+    assert_eq!(set.len(), 0);
+    return Timings {insert, lookup, remove};
+}
+
+fn main() {
+    let n = std::env::args()
+        .skip(1)
+        .find(|arg| !arg.starts_with('-'))
+        .map(|arg| arg.parse::<usize>().expect("n must be a number"))
+        .unwrap_or(200_000);
+    let mut rng = StdRng::seed_from_u64(0x5eed);
+
+    /* Even keys are inserted, odd keys are only ever looked up. */
+    let mut inserts = (0..n as u64).map(|i| i * 2).collect::<Vec<_>>();
+    inserts.shuffle(&mut rng);
+    let mut lookups = (0..n as u64 * 2).collect::<Vec<_>>();
+    lookups.shuffle(&mut rng);
+    let mut removes = inserts.clone();
+    removes.shuffle(&mut rng);
+
+    println!("{} keys", n);
+    println!("{:<12} {:>12} {:>12} {:>12}", "", "insert", "lookup", "remove");
+    let rows = [
+        ("arena", run::<RBTree<u64>>(&inserts, &lookups, &removes)),
+        ("rc", run::<RcTree<u64>>(&inserts, &lookups, &removes)),
+        ("BTreeSet", run::<BTreeSet<u64>>(&inserts, &lookups, &removes))
+    ];
+    for (name, t) in rows {
+        println!("{:<12} {:>10.1?} {:>10.1?} {:>10.1?}", name, t.insert, t.lookup, t.remove);
+    }
+}
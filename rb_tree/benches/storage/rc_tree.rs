@@ -0,0 +1,379 @@
+/* The node representation `RBMap` used before the arena: every node is an
+ * `Rc<RefCell<...>>` with a weak parent link. Only what the benchmark needs is
+ * kept (a set with subtree sizes, no values or summaries); the balancing code
+ * is otherwise unchanged. */
+
+use std::cell::RefCell;
+use std::rc::{Rc, Weak};
+
+pub struct RcTree<K: PartialOrd> {
+    root: RBNode<K>,
+    len: usize
+}
+
+type RBNode<K> = Option<Rc<RefCell<RBNodeInternal<K>>>>;
+type WeakRBNode<K> = Option<Weak<RefCell<RBNodeInternal<K>>>>;
+
+struct RBNodeInternal<K: PartialOrd> {
+    color: RBColor,
+    key: K,
+    size: usize,
+    right: RBNode<K>,
+    left: RBNode<K>,
+    p: WeakRBNode<K>
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum RBColor {
+    Red,
+    Black
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum NodeChildType {
+    Left,
+    Right
+}
+
+impl<K: PartialOrd> PartialEq for RBNodeInternal<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key && self.left == other.left && self.right == other.right
+    }
+}
+
+fn clone_node<K: PartialOrd>(node: &RBNode<K>) -> RBNode<K> {
+    node.as_ref().map(Rc::clone)
+}
+
+fn get_left<K: PartialOrd>(node: &RBNode<K>) -> RBNode<K> {
+    node.as_ref().and_then(|val| clone_node(&val.borrow().left))
+}
+
+fn get_right<K: PartialOrd>(node: &RBNode<K>) -> RBNode<K> {
+    node.as_ref().and_then(|val| clone_node(&val.borrow().right))
+}
+
+fn get_parent<K: PartialOrd>(node: &RBNode<K>) -> WeakRBNode<K> {
+    node.as_ref().and_then(|val| val.borrow().p.as_ref().map(Weak::clone))
+}
+
+fn get_color<K: PartialOrd>(node: &RBNode<K>) -> RBColor {
+    node.as_ref().map_or(RBColor::Black, |val| val.borrow().color)
+}
+
+fn get_size<K: PartialOrd>(node: &RBNode<K>) -> usize {
+    node.as_ref().map_or(0, |val| val.borrow().size)
+}
+
+fn update_node<K: PartialOrd>(node: &RBNode<K>) {
+    if let Some(val) = node.as_ref() {
+        let size = {
+            let n = val.borrow();
+            1 + get_size(&n.left) + get_size(&n.right)
+        };
+        val.borrow_mut().size = size;
+    }
+}
+
+fn update_nodes_to_root<K: PartialOrd>(node: &RBNode<K>) {
+    let mut x = clone_node(node);
+    while x.is_some() {
+        update_node(&x);
+        x = to_strong(&get_parent(&x));
+    }
+}
+
+fn set_left<K: PartialOrd>(node: &RBNode<K>, left: RBNode<K>) {
+    if let Some(val) = node.as_ref() {
+        val.borrow_mut().left = left;
+    }
+}
+
+fn set_right<K: PartialOrd>(node: &RBNode<K>, right: RBNode<K>) {
+    if let Some(val) = node.as_ref() {
+        val.borrow_mut().right = right;
+    }
+}
+
+fn set_parent<K: PartialOrd>(node: &RBNode<K>, parent: WeakRBNode<K>) {
+    if let Some(val) = node.as_ref() {
+        val.borrow_mut().p = parent;
+    }
+}
+
+fn set_color<K: PartialOrd>(node: &RBNode<K>, color: RBColor) {
+    if let Some(val) = node.as_ref() {
+        val.borrow_mut().color = color;
+    }
+}
+
+fn to_weak<K: PartialOrd>(node: &RBNode<K>) -> WeakRBNode<K> {
+    node.as_ref().map(Rc::downgrade)
+}
+
+fn to_strong<K: PartialOrd>(node: &WeakRBNode<K>) -> RBNode<K> {
+    node.as_ref().map(|val| Weak::upgrade(val).expect("INVALID STATE!"))
+}
+
+fn get_minimum<K: PartialOrd>(z: &RBNode<K>) -> RBNode<K> {
+    let mut z_node = clone_node(z);
+    while get_left(&z_node).is_some() {
+        z_node = get_left(&z_node);
+    }
+    return z_node;
+}
+
+impl<K: PartialOrd> RcTree<K> {
+    pub fn new() -> RcTree<K> {
+        RcTree {root: None, len: 0}
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn insert(&mut self, key: K) {
+        let mut y: RBNode<K> = None;
+        let mut x: RBNode<K> = clone_node(&self.root);
+        while let Some(rc_node) = clone_node(&x) {
+            if key == rc_node.borrow().key {
+                return;
+            }
+            y = x;
+            if key < rc_node.borrow().key {
+                x = clone_node(&rc_node.borrow().left);
+            } else {
+                x = clone_node(&rc_node.borrow().right);
+            }
+        }
+        let z = RBNodeInternal {color: RBColor::Red, key, size: 1, right: None, left: None, p: to_weak(&y)};
+        let z_node = Some(Rc::new(RefCell::new(z)));
+        match y.as_ref() {
+            None => self.root = clone_node(&z_node),
+            Some(rc) => {
+                let goes_left = z_node.as_ref().unwrap().borrow().key < rc.borrow().key;
+                if goes_left {
+                    rc.borrow_mut().left = clone_node(&z_node);
+                } else {
+                    rc.borrow_mut().right = clone_node(&z_node);
+                }
+            }
+        }
+        update_nodes_to_root(&y);
+        self.insert_fixup(z_node);
+        self.len += 1;
+    }
+
+    fn insert_fixup(&mut self, mut z: RBNode<K>) {
+        while get_color(&to_strong(&get_parent(&z))) == RBColor::Red {
+            if to_strong(&get_parent(&z)) == get_left(&to_strong(&get_parent(&to_strong(&get_parent(&z))))) {
+                let y = get_right(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
+                if get_color(&y) == RBColor::Red {
+                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
+                    set_color(&y, RBColor::Black);
+                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
+                    z = to_strong(&get_parent(&to_strong(&get_parent(&z))));
+                } else {
+                    if z == get_right(&to_strong(&get_parent(&z))) {
+                        z = to_strong(&get_parent(&z));
+                        self.left_rotate(&z);
+                    }
+                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
+                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
+                    self.right_rotate(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
+                }
+            } else {
+                let y = get_left(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
+                if get_color(&y) == RBColor::Red {
+                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
+                    set_color(&y, RBColor::Black);
+                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
+                    z = to_strong(&get_parent(&to_strong(&get_parent(&z))));
+                } else {
+                    if z == get_left(&to_strong(&get_parent(&z))) {
+                        z = to_strong(&get_parent(&z));
+                        self.right_rotate(&z);
+                    }
+                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
+                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
+                    self.left_rotate(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
+                }
+            }
+        }
+        set_color(&self.root, RBColor::Black);
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return self.find(key).is_some();
+    }
+
+    fn find(&self, key: &K) -> RBNode<K> {
+        let mut x = clone_node(&self.root);
+        while let Some(rc) = clone_node(&x) {
+            let node_key = &rc.borrow().key;
+            if key < node_key {
+                x = clone_node(&rc.borrow().left);
+            } else if key == node_key {
+                return x;
+            } else {
+                x = clone_node(&rc.borrow().right);
+            }
+        }
+        return None;
+    }
+
+    pub fn remove(&mut self, key: &K) {
+        let z = self.find(key);
+        if z.is_some() {
+            self.remove_node(&z);
+            self.len -= 1;
+        }
+    }
+
+    fn remove_node(&mut self, z: &RBNode<K>) {
+        let mut y = clone_node(z);
+        let mut y_original_color = get_color(&y);
+        let mut x_parent: RBNode<K>;
+        let x_parent_relationship: NodeChildType;
+        let x: RBNode<K>;
+        if get_left(z).is_none() || get_right(z).is_none() {
+            x = if get_left(z).is_none() {get_right(z)} else {get_left(z)};
+            x_parent = to_strong(&get_parent(z));
+            x_parent_relationship = if z == &get_left(&x_parent) {
+                NodeChildType::Left
+            } else {
+                NodeChildType::Right
+            };
+            self.transplant(z, &x);
+        } else {
+            y = get_minimum(&get_right(z));
+            y_original_color = get_color(&y);
+            x = get_right(&y);
+            x_parent = to_strong(&get_parent(&y));
+            if y != get_right(z) {
+                self.transplant(&y, &get_right(&y));
+                set_right(&y, get_right(z));
+                set_parent(&get_right(&y), to_weak(&y));
+                x_parent_relationship = NodeChildType::Left;
+            } else {
+                set_parent(&x, to_weak(&y));
+                x_parent = clone_node(&y);
+                x_parent_relationship = NodeChildType::Right;
+            }
+            self.transplant(z, &y);
+            set_left(&y, get_left(z));
+            set_parent(&get_left(&y), to_weak(&y));
+            set_color(&y, get_color(z));
+        }
+        update_nodes_to_root(&x_parent);
+        if y_original_color == RBColor::Black {
+            self.remove_fixup(x, x_parent, x_parent_relationship);
+        }
+    }
+
+    fn remove_fixup(&mut self, mut x: RBNode<K>, mut x_parent: RBNode<K>, mut x_parent_relationship: NodeChildType) {
+        while x != self.root && get_color(&x) == RBColor::Black {
+            if x_parent_relationship == NodeChildType::Left {
+                let mut w = get_right(&x_parent);
+                if get_color(&w) == RBColor::Red {
+                    set_color(&w, RBColor::Black);
+                    set_color(&x_parent, RBColor::Red);
+                    self.left_rotate(&x_parent);
+                    w = get_right(&x_parent);
+                }
+                if get_color(&get_left(&w)) == RBColor::Black && get_color(&get_right(&w)) == RBColor::Black {
+                    set_color(&w, RBColor::Red);
+                    x = x_parent;
+                    x_parent = to_strong(&get_parent(&x));
+                    x_parent_relationship = if x == get_left(&x_parent) {NodeChildType::Left} else {NodeChildType::Right};
+                } else {
+                    if get_color(&get_right(&w)) == RBColor::Black {
+                        set_color(&get_left(&w), RBColor::Black);
+                        set_color(&w, RBColor::Red);
+                        self.right_rotate(&w);
+                        w = get_right(&x_parent);
+                    }
+                    set_color(&w, get_color(&x_parent));
+                    set_color(&x_parent, RBColor::Black);
+                    set_color(&get_right(&w), RBColor::Black);
+                    self.left_rotate(&x_parent);
+                    break;
+                }
+            } else {
+                let mut w = get_left(&x_parent);
+                if get_color(&w) == RBColor::Red {
+                    set_color(&w, RBColor::Black);
+                    set_color(&x_parent, RBColor::Red);
+                    self.right_rotate(&x_parent);
+                    w = get_left(&x_parent);
+                }
+                if get_color(&get_left(&w)) == RBColor::Black && get_color(&get_right(&w)) == RBColor::Black {
+                    set_color(&w, RBColor::Red);
+                    x = x_parent;
+                    x_parent = to_strong(&get_parent(&x));
+                    x_parent_relationship = if x == get_left(&x_parent) {NodeChildType::Left} else {NodeChildType::Right};
+                } else {
+                    if get_color(&get_left(&w)) == RBColor::Black {
+                        set_color(&get_right(&w), RBColor::Black);
+                        set_color(&w, RBColor::Red);
+                        self.left_rotate(&w);
+                        w = get_left(&x_parent);
+                    }
+                    set_color(&w, get_color(&x_parent));
+                    set_color(&x_parent, RBColor::Black);
+                    set_color(&get_left(&w), RBColor::Black);
+                    self.right_rotate(&x_parent);
+                    break;
+                }
+            }
+        }
+        set_color(&x, RBColor::Black);
+    }
+
+    fn transplant(&mut self, u: &RBNode<K>, v: &RBNode<K>) {
+        if get_parent(u).is_none() {
+            self.root = clone_node(v);
+        } else if u == &get_left(&to_strong(&get_parent(u))) {
+            set_left(&to_strong(&get_parent(u)), clone_node(v));
+        } else {
+            set_right(&to_strong(&get_parent(u)), clone_node(v));
+        }
+        set_parent(v, get_parent(u));
+    }
+
+    fn left_rotate(&mut self, x: &RBNode<K>) {
+        let y = get_right(x);
+        set_right(x, get_left(&y));
+        set_parent(&get_left(&y), to_weak(x));
+        set_parent(&y, get_parent(x));
+        if get_parent(x).is_none() {
+            self.root = clone_node(&y);
+        } else if x == &get_left(&to_strong(&get_parent(x))) {
+            set_left(&to_strong(&get_parent(x)), clone_node(&y));
+        } else {
+            set_right(&to_strong(&get_parent(x)), clone_node(&y));
+        }
+        set_left(&y, clone_node(x));
+        set_parent(x, to_weak(&y));
+        update_node(x);
+        update_node(&y);
+    }
+
+    fn right_rotate(&mut self, x: &RBNode<K>) {
+        let y = get_left(x);
+        set_left(x, get_right(&y));
+        set_parent(&get_right(&y), to_weak(x));
+        set_parent(&y, get_parent(x));
+        if get_parent(x).is_none() {
+            self.root = clone_node(&y);
+        } else if x == &get_left(&to_strong(&get_parent(x))) {
+            set_left(&to_strong(&get_parent(x)), clone_node(&y));
+        } else {
+            set_right(&to_strong(&get_parent(x)), clone_node(&y));
+        }
+        set_right(&y, clone_node(x));
+        set_parent(x, to_weak(&y));
+        update_node(x);
+        update_node(&y);
+    }
+}
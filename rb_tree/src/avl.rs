@@ -0,0 +1,252 @@
+use alloc::boxed::Box;
+use core::cmp::Ordering;
+use core::ops::RangeBounds;
+
+use crate::ordered_set::{BinaryNode, NodeIter, OrderedSet};
+
+type Link<K> = Option<Box<AvlNode<K>>>;
+
+/* An ordered set balanced as an AVL tree: the heights of the two subtrees of
+ * every node differ by at most one. It is kept as a plain tree of boxed
+ * nodes, to compare against `RBTree` through `OrderedSet`. */
+pub struct AvlTree<K> {
+    root: Link<K>,
+    len: usize
+}
+
+pub struct AvlNode<K> {
+    key: K,
+    height: u8, /* of the subtree rooted here; a leaf has height 1 */
+    left: Link<K>,
+    right: Link<K>
+}
+
+impl<K> BinaryNode<K> for AvlNode<K> {
+    fn key(&self) -> &K {
+        return &self.key;
+    }
+
+    fn left(&self) -> Option<&Self> {
+        return self.left.as_deref();
+    }
+
+    fn right(&self) -> Option<&Self> {
+        return self.right.as_deref();
+    }
+}
+
+fn height<K>(x: &Link<K>) -> u8 {
+    return x.as_ref().map_or(0, |node| node.height);
+}
+
+impl<K> AvlNode<K> {
+    fn update(&mut self) {
+        self.height = 1 + height(&self.left).max(height(&self.right));
+    }
+
+    /* Returns: the left height minus the right height. */
+    fn balance(&self) -> i16 {
+        return height(&self.left) as i16 - height(&self.right) as i16;
+    }
+}
+
+fn rotate_left<K>(mut x: Box<AvlNode<K>>) -> Box<AvlNode<K>> {
+    let mut y = x.right.take().expect("INVALID STATE!");
+    x.right = y.left.take();
+    x.update();
+    y.left = Some(x);
+    y.update();
+    return y;
+}
+
+fn rotate_right<K>(mut x: Box<AvlNode<K>>) -> Box<AvlNode<K>> {
+    let mut y = x.left.take().expect("INVALID STATE!");
+    x.left = y.right.take();
+    x.update();
+    y.right = Some(x);
+    y.update();
+    return y;
+}
+
+/* Restores the AVL property at `x`, whose subtrees are balanced and differ
+ * in height by at most two. Returns: the new root of the subtree. */
+fn rebalance<K>(mut x: Box<AvlNode<K>>) -> Box<AvlNode<K>> {
+    x.update();
+    if x.balance() > 1 {
+        if x.left.as_ref().expect("INVALID STATE!").balance() < 0 {
+            x.left = x.left.take().map(rotate_left);
+        }
+        return rotate_right(x);
+    }
+    if x.balance() < -1 {
+        if x.right.as_ref().expect("INVALID STATE!").balance() > 0 {
+            x.right = x.right.take().map(rotate_right);
+        }
+        return rotate_left(x);
+    }
+    return x;
+}
+
+/* Returns: whether `key` was added under `link`. */
+fn insert<K: Ord>(link: &mut Link<K>, key: K) -> bool {
+    let Some(mut node) = link.take() else {
+        *link = Some(Box::new(AvlNode {key, height: 1, left: None, right: None}));
+        return true;
+    };
+    let added = match key.cmp(&node.key) {
+        Ordering::Less => insert(&mut node.left, key),
+        Ordering::Equal => false,
+        Ordering::Greater => insert(&mut node.right, key)
+    };
+    *link = Some(rebalance(node));
+    return added;
+}
+
+/* Returns: whether `key` was removed from under `link`. */
+fn remove<K: Ord>(link: &mut Link<K>, key: &K) -> bool {
+    let Some(mut node) = link.take() else {
+        return false;
+    };
+    let removed = match key.cmp(&node.key) {
+        Ordering::Less => remove(&mut node.left, key),
+        Ordering::Greater => remove(&mut node.right, key),
+        Ordering::Equal => {
+            *link = match (node.left.take(), node.right.take()) {
+                (None, right) => right,
+                (left, None) => left,
+                (left, Some(right)) => {
+                    /* The successor takes the removed node's place. */
+                    let (mut successor, rest) = take_min(right);
+                    successor.left = left;
+                    successor.right = rest;
+                    Some(rebalance(successor))
+                }
+            };
+            return true;
+        }
+    };
+    *link = Some(rebalance(node));
+    return removed;
+}
+
+/* Returns: the minimum node of the subtree at `x`, unlinked, and the rest
+ * of the subtree. */
+fn take_min<K>(mut x: Box<AvlNode<K>>) -> (Box<AvlNode<K>>, Link<K>) {
+    match x.left.take() {
+        None => {
+            let rest = x.right.take();
+            return (x, rest);
+        }
+        Some(left) => {
+            let (min, rest) = take_min(left);
+            x.left = rest;
+            return (min, Some(rebalance(x)));
+        }
+    }
+}
+
+impl<K> Default for AvlTree<K> {
+    fn default() -> Self {
+        AvlTree {root: None, len: 0}
+    }
+}
+
+impl<K: Ord> AvlTree<K> {
+    pub fn new() -> AvlTree<K> {
+        return AvlTree::default();
+    }
+
+    /* Returns: whether `key` was added. */
+    pub fn insert(&mut self, key: K) -> bool {
+        let added = insert(&mut self.root, key);
+        self.len += added as usize;
+        return added;
+    }
+
+    /* Returns: whether `key` was present. */
+    pub fn remove(&mut self, key: &K) -> bool {
+        let removed = remove(&mut self.root, key);
+        self.len -= removed as usize;
+        return removed;
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let mut x = self.root.as_deref();
+        while let Some(node) = x {
+            x = match key.cmp(&node.key) {
+                Ordering::Less => node.left.as_deref(),
+                Ordering::Equal => return true,
+                Ordering::Greater => node.right.as_deref()
+            };
+        }
+        return false;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /* Returns: an iterator over the keys in ascending order. */
+    pub fn iter(&self) -> NodeIter<'_, K, AvlNode<K>> {
+        return self.range(..);
+    }
+
+    /* Returns: an iterator over the keys that fall in `range`, in ascending order. */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> NodeIter<'_, K, AvlNode<K>> {
+        return NodeIter::new(self.root.as_deref(), range);
+    }
+
+    /* Checks the order, the stored heights and the AVL balance of every node. */
+    #[allow(dead_code)]
+    pub(crate) fn is_avl_tree(&self) -> bool {
+        /* Returns: the height of the subtree at `x`, or None if it is broken. */
+        fn check<K: Ord>(x: &Link<K>, low: Option<&K>, high: Option<&K>) -> Option<u8> {
+            let Some(node) = x else {
+                return Some(0);
+            };
+            if low.is_some_and(|low| node.key <= *low) || high.is_some_and(|high| node.key >= *high) {
+                return None;
+            }
+            let left = check(&node.left, low, Some(&node.key))?;
+            let right = check(&node.right, Some(&node.key), high)?;
+            if left.abs_diff(right) > 1 || node.height != 1 + left.max(right) {
+                return None;
+            }
+            return Some(node.height);
+        }
+        return check(&self.root, None, None).is_some() && self.iter().count() == self.len;
+    }
+}
+
+impl<K: Ord> OrderedSet<K> for AvlTree<K> {
+    type Iter<'a> = NodeIter<'a, K, AvlNode<K>> where Self: 'a, K: 'a;
+    type Range<'a> = NodeIter<'a, K, AvlNode<K>> where Self: 'a, K: 'a;
+
+    fn insert(&mut self, key: K) -> bool {
+        return AvlTree::insert(self, key);
+    }
+
+    fn remove(&mut self, key: &K) -> bool {
+        return AvlTree::remove(self, key);
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        return AvlTree::contains(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return AvlTree::len(self);
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        return AvlTree::iter(self);
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Self::Range<'_> {
+        return AvlTree::range(self, range);
+    }
+}
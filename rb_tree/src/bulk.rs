@@ -0,0 +1,190 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use crate::join::Subtree;
+use crate::{Comparator, NaturalOrder, RBColor, RBMap, RBNode, RBNodeInternal, RBTree, Summary};
+
+/* Bulk construction. Sorted input is built straight into a balanced shape in
+ * O(n), with no descents and no fixups. */
+impl<K, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Moves entries from the front of `iter` onto `run` while their keys keep
+     * ascending. An entry equal to the previous one replaces its value, as
+     * `insert` would. Returns: the first entry that is out of order, if any. */
+    fn take_run<I: Iterator<Item = (K, V)>>(&self, iter: &mut I, run: &mut Vec<(K, V)>) -> Option<(K, V)> {
+        for (key, value) in iter {
+            if let Some(last) = run.last_mut() {
+                match self.compare(&last.0, &key) {
+                    Ordering::Less => {}
+                    Ordering::Equal => {
+                        last.1 = value;
+                        continue;
+                    }
+                    Ordering::Greater => return Some((key, value))
+                }
+            }
+            run.push((key, value));
+        }
+        return None;
+    }
+
+    /* Builds the entries, which must be in ascending key order, into a tree
+     * in this map's arena. Each node splits its entries as evenly as possible,
+     * so every empty link is on one of the two lowest levels. Coloring the
+     * lowest level red when it is incomplete then gives every path the same
+     * number of black nodes. */
+    fn build_sorted<I: ExactSizeIterator<Item = (K, V)>>(&mut self, mut entries: I) -> Subtree<K, V, S> {
+        let n = entries.len();
+        if n == 0 {
+            return Subtree::empty();
+        }
+        let depth = n.ilog2() as usize; /* depth of the lowest level */
+        let red_depth = if (n + 1).is_power_of_two() {None} else {Some(depth)};
+        let root = self.build_balanced(&mut entries, n, 0, red_depth);
+        return Subtree {root, height: if red_depth.is_some() {depth} else {depth + 1}};
+    }
+
+    /* Builds the next `n` entries into a subtree whose root is at `depth`. */
+    fn build_balanced<I: Iterator<Item = (K, V)>>(&mut self, entries: &mut I, n: usize, depth: usize, red_depth: Option<usize>) -> RBNode<K, V, S> {
+        if n == 0 {
+            return None;
+        }
+        let left_n = (n - 1) / 2;
+        let left = self.build_balanced(entries, left_n, depth + 1, red_depth);
+        let (key, value) = entries.next().expect("INVALID STATE!");
+        let right = self.build_balanced(entries, n - 1 - left_n, depth + 1, red_depth);
+        let x = Some(self.allocate(RBNodeInternal {
+            color: if red_depth == Some(depth) {RBColor::Red} else {RBColor::Black},
+            summary: S::from_entry(&key, &value),
+            dirty: false,
+            key,
+            value,
+            size: 1,
+            right,
+            left,
+            p: None
+        }));
+        self.set_parent(left, x);
+        self.set_parent(right, x);
+        self.update_node(x);
+        return x;
+    }
+
+    /* `from_sorted_iter_by` for maps of any summary type. */
+    pub(crate) fn build_from_sorted<I: IntoIterator<Item = (K, V)>>(cmp: C, iter: I) -> Self {
+        let mut map = RBMap {nodes: Vec::new(), free: None, root: None, len: 0, cmp};
+        let mut iter = iter.into_iter();
+        let mut run = Vec::with_capacity(iter.size_hint().0);
+        assert!(map.take_run(&mut iter, &mut run).is_none(), "from_sorted_iter: keys must be in ascending order");
+        let tree = map.build_sorted(run.into_iter());
+        map.set_root(tree);
+        return map;
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Adds an ascending run of entries and empties `run`. A run that lies
+     * wholly after (or before) the current keys is built in O(k) and joined
+     * on in O(log n); anything else is inserted an entry at a time, each
+     * searched for from the previous one. */
+    fn extend_run(&mut self, run: &mut Vec<(K, V)>) {
+        let (Some(first), Some(last)) = (run.first(), run.last()) else {
+            return;
+        };
+        let (min, max) = (self.get_minimum(self.root), self.get_maximum(self.root));
+        if max.is_none_or(|max| self.compare(&self.node(max).key, &first.0) == Ordering::Less) {
+            let tree = self.take_root();
+            let mut entries = run.drain(..);
+            let k = self.build_balanced(&mut entries, 1, 0, None).expect("INVALID STATE!");
+            let right = self.build_sorted(entries);
+            let tree = self.join_with(tree, k, right);
+            self.set_root(tree);
+        } else if min.is_some_and(|min| self.compare(&last.0, &self.node(min).key) == Ordering::Less) {
+            let tree = self.take_root();
+            let (key, value) = run.pop().expect("INVALID STATE!");
+            let k = self.build_balanced(&mut [(key, value)].into_iter(), 1, 0, None).expect("INVALID STATE!");
+            let left = self.build_sorted(run.drain(..));
+            let tree = self.join_with(left, k, tree);
+            self.set_root(tree);
+        } else {
+            /* Each entry is searched for from the one before it, and sizes
+             * and summaries are settled once at the end. */
+            let (mut hint, mut around) = (None, None);
+            for (key, value) in run.drain(..) {
+                let (x, _, gap) = self.insert_from(hint, around, key, value);
+                (hint, around) = (Some(x), gap);
+            }
+            self.settle();
+        }
+    }
+}
+
+impl<K: core::cmp::PartialOrd, V> RBMap<K, V> {
+    /* Returns: a map holding the entries of `iter`, which must be in ascending
+     * key order, built in O(n). */
+    pub fn from_sorted_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> RBMap<K, V> {
+        return RBMap::build_from_sorted(NaturalOrder, iter);
+    }
+}
+
+impl<K, V, C: Comparator<K>> RBMap<K, V, (), C> {
+    /* Returns: a map holding the entries of `iter` in the order given by
+     * `cmp`, built in O(n). Equal keys keep the first key and the last value.
+     * Panics if the keys are not in ascending order. */
+    pub fn from_sorted_iter_by<I: IntoIterator<Item = (K, V)>>(cmp: C, iter: I) -> RBMap<K, V, (), C> {
+        return RBMap::build_from_sorted(cmp, iter);
+    }
+}
+
+/* Inserts the entries in order. Ascending runs in the input are gathered and
+ * handed to `extend_run`, so already-sorted input is appended in O(n). */
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>> Extend<(K, V)> for RBMap<K, V, S, C> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        let mut iter = iter.into_iter();
+        let mut run = Vec::new();
+        loop {
+            let next = self.take_run(&mut iter, &mut run);
+            self.extend_run(&mut run);
+            match next {
+                Some(entry) => run.push(entry),
+                None => return
+            }
+        }
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K> + Default> FromIterator<(K, V)> for RBMap<K, V, S, C> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut map = RBMap::default();
+        map.extend(iter);
+        return map;
+    }
+}
+
+impl<K, C: Comparator<K>> RBTree<K, (), C> {
+    /* Returns: a set holding the keys of `iter` in the order given by `cmp`,
+     * built in O(n). Panics if the keys are not in ascending order. */
+    pub fn from_sorted_iter_by<I: IntoIterator<Item = K>>(cmp: C, iter: I) -> RBTree<K, (), C> {
+        return RBTree {map: RBMap::from_sorted_iter_by(cmp, iter.into_iter().map(|key| (key, ())))};
+    }
+}
+
+impl<K: core::cmp::PartialOrd> RBTree<K> {
+    /* Returns: a set holding the keys of `iter`, which must be in ascending
+     * order, built in O(n). */
+    pub fn from_sorted_iter<I: IntoIterator<Item = K>>(iter: I) -> RBTree<K> {
+        return RBTree::from_sorted_iter_by(NaturalOrder, iter);
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>> Extend<K> for RBTree<K, S, C> {
+    fn extend<I: IntoIterator<Item = K>>(&mut self, iter: I) {
+        self.map.extend(iter.into_iter().map(|key| (key, ())));
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K> + Default> FromIterator<K> for RBTree<K, S, C> {
+    fn from_iter<I: IntoIterator<Item = K>>(iter: I) -> Self {
+        return RBTree {map: iter.into_iter().map(|key| (key, ())).collect()};
+    }
+}
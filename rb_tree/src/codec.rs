@@ -0,0 +1,473 @@
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::{Comparator, NaturalOrder, RBMap, RBTree};
+
+/* The on-disk format of an `RBMap` or `RBTree`, version 1. Integers in the
+ * header are little-endian.
+ *
+ *     magic     4 bytes   b"RBTR"
+ *     version   1 byte    1
+ *     count     8 bytes   number of entries
+ *     length    8 bytes   number of payload bytes
+ *     payload   `length` bytes: each key followed by its value, in ascending
+ *               key order, as written by `Codec::encode`
+ *     checksum  4 bytes   CRC-32 (IEEE) of everything before it
+ *
+ * A set is stored as a map whose values are `()`, which take no bytes. */
+const MAGIC: &[u8; 4] = b"RBTR";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 21;
+
+/* How keys and values are written to and read back from the binary format.
+ * Numbers are fixed-width little-endian; strings and sequences are prefixed
+ * with their length as an LEB128 varint. */
+pub trait Codec: Sized {
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /* Reads one value from the front of `input` and advances it past the
+     * bytes read. */
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError>;
+}
+
+/* Why bytes could not be loaded as a tree. */
+#[derive(Debug)]
+pub enum DecodeError {
+    #[cfg(feature = "std")]
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated, /* the input ended early */
+    ChecksumMismatch,
+    InvalidData(&'static str), /* a key or value could not be decoded */
+    Unsorted /* the keys are not strictly ascending */
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "std")]
+            DecodeError::Io(error) => write!(f, "could not read tree: {}", error),
+            DecodeError::BadMagic => write!(f, "not a serialized tree"),
+            DecodeError::UnsupportedVersion(version) => write!(f, "unsupported format version {}", version),
+            DecodeError::Truncated => write!(f, "serialized tree is truncated"),
+            DecodeError::ChecksumMismatch => write!(f, "serialized tree is corrupt (checksum mismatch)"),
+            DecodeError::InvalidData(what) => write!(f, "serialized tree is corrupt ({})", what),
+            DecodeError::Unsorted => write!(f, "serialized tree is corrupt (keys out of order)")
+        }
+    }
+}
+
+impl core::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            #[cfg(feature = "std")]
+            DecodeError::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl From<io::Error> for DecodeError {
+    fn from(error: io::Error) -> Self {
+        DecodeError::Io(error)
+    }
+}
+
+/* Returns: the first `n` bytes of `input`, advancing it past them. */
+fn take<'a>(input: &mut &'a [u8], n: usize) -> Result<&'a [u8], DecodeError> {
+    if input.len() < n {
+        return Err(DecodeError::Truncated);
+    }
+    let (head, rest) = input.split_at(n);
+    *input = rest;
+    return Ok(head);
+}
+
+fn encode_len(mut len: usize, out: &mut Vec<u8>) {
+    while len >= 0x80 {
+        out.push(len as u8 | 0x80);
+        len >>= 7;
+    }
+    out.push(len as u8);
+}
+
+fn decode_len(input: &mut &[u8]) -> Result<usize, DecodeError> {
+    let mut len: u64 = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = take(input, 1)?[0];
+        len |= ((byte & 0x7f) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return usize::try_from(len).map_err(|_| DecodeError::InvalidData("length too large"));
+        }
+    }
+    return Err(DecodeError::InvalidData("length too large"));
+}
+
+const CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 != 0 {0xedb88320 ^ (c >> 1)} else {c >> 1};
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    return table;
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    return !bytes.iter().fold(!0, |crc, &b| CRC_TABLE[((crc ^ b as u32) & 0xff) as usize] ^ (crc >> 8));
+}
+
+macro_rules! number_codec {
+    ($($t:ty),*) => {
+        $(
+            impl Codec for $t {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+                    let bytes = take(input, core::mem::size_of::<$t>())?;
+                    return Ok(<$t>::from_le_bytes(bytes.try_into().expect("INVALID STATE!")));
+                }
+            }
+        )*
+    };
+}
+
+number_codec!(u8, u16, u32, u64, u128, i8, i16, i32, i64, i128, f32, f64);
+
+/* Sizes are written as 64 bits so files move between platforms. */
+impl Codec for usize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        return usize::try_from(u64::decode(input)?).map_err(|_| DecodeError::InvalidData("usize out of range"));
+    }
+}
+
+impl Codec for isize {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as i64).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        return isize::try_from(i64::decode(input)?).map_err(|_| DecodeError::InvalidData("isize out of range"));
+    }
+}
+
+impl Codec for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match take(input, 1)?[0] {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(DecodeError::InvalidData("invalid bool"))
+        }
+    }
+}
+
+impl Codec for char {
+    fn encode(&self, out: &mut Vec<u8>) {
+        (*self as u32).encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        return char::from_u32(u32::decode(input)?).ok_or(DecodeError::InvalidData("invalid char"));
+    }
+}
+
+impl Codec for () {
+    fn encode(&self, _: &mut Vec<u8>) {}
+
+    fn decode(_: &mut &[u8]) -> Result<Self, DecodeError> {
+        return Ok(());
+    }
+}
+
+impl Codec for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        let bytes = take(input, len)?;
+        return String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidData("invalid UTF-8"));
+    }
+}
+
+impl<T: Codec> Codec for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        let len = decode_len(input)?;
+        /* `len` is not trusted for the allocation; the items themselves run
+         * out of input if it is wrong. */
+        let mut items = Vec::with_capacity(len.min(input.len()));
+        for _ in 0..len {
+            items.push(T::decode(input)?);
+        }
+        return Ok(items);
+    }
+}
+
+impl<T: Codec> Codec for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(value) => {
+                out.push(1);
+                value.encode(out);
+            }
+        }
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        match take(input, 1)?[0] {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(input)?)),
+            _ => Err(DecodeError::InvalidData("invalid option tag"))
+        }
+    }
+}
+
+impl<A: Codec, B: Codec> Codec for (A, B) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        return Ok((A::decode(input)?, B::decode(input)?));
+    }
+}
+
+impl<A: Codec, B: Codec, C: Codec> Codec for (A, B, C) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+        self.2.encode(out);
+    }
+
+    fn decode(input: &mut &[u8]) -> Result<Self, DecodeError> {
+        return Ok((A::decode(input)?, B::decode(input)?, C::decode(input)?));
+    }
+}
+
+impl<K: Codec, V: Codec, S, C> RBMap<K, V, S, C> {
+    /* Returns: the map in the binary format described at the top of this file. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(HEADER_LEN + 4);
+        out.extend_from_slice(MAGIC);
+        out.push(VERSION);
+        (self.len as u64).encode(&mut out);
+        (0u64).encode(&mut out); /* the payload length, filled in below */
+        for (key, value) in self.iter() {
+            key.encode(&mut out);
+            value.encode(&mut out);
+        }
+        let length = (out.len() - HEADER_LEN) as u64;
+        out[HEADER_LEN - 8..HEADER_LEN].copy_from_slice(&length.to_le_bytes());
+        let checksum = crc32(&out);
+        checksum.encode(&mut out);
+        return out;
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write>(&self, mut writer: W) -> io::Result<()> {
+        return writer.write_all(&self.to_bytes());
+    }
+}
+
+impl<K: Codec, V: Codec, C: Comparator<K>> RBMap<K, V, (), C> {
+    /* Returns: the map stored in `bytes`, ordered by `cmp`, which must be the
+     * order it was saved in. The entries are checked and then built in O(n).
+     * Any damage to the input is reported as an error. */
+    pub fn from_bytes_by(cmp: C, bytes: &[u8]) -> Result<RBMap<K, V, (), C>, DecodeError> {
+        let mut input = bytes;
+        if take(&mut input, MAGIC.len())? != MAGIC {
+            return Err(DecodeError::BadMagic);
+        }
+        let version = u8::decode(&mut input)?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+        let count = u64::decode(&mut input)?;
+        let length = usize::decode(&mut input)?;
+        let mut payload = take(&mut input, length)?;
+        let checksum = u32::decode(&mut input)?;
+        if !input.is_empty() {
+            return Err(DecodeError::InvalidData("trailing bytes"));
+        }
+        if checksum != crc32(&bytes[..HEADER_LEN + length]) {
+            return Err(DecodeError::ChecksumMismatch);
+        }
+
+        let mut entries: Vec<(K, V)> = Vec::with_capacity((count as usize).min(payload.len()));
+        for _ in 0..count {
+            let key = K::decode(&mut payload)?;
+            let value = V::decode(&mut payload)?;
+            if entries.last().is_some_and(|(last, _)| cmp.compare(last, &key) != Ordering::Less) {
+                return Err(DecodeError::Unsorted);
+            }
+            entries.push((key, value));
+        }
+        if !payload.is_empty() {
+            return Err(DecodeError::InvalidData("payload longer than its entries"));
+        }
+        return Ok(RBMap::from_sorted_iter_by(cmp, entries));
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_from_by<R: io::Read>(cmp: C, mut reader: R) -> Result<RBMap<K, V, (), C>, DecodeError> {
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        return RBMap::from_bytes_by(cmp, &bytes);
+    }
+}
+
+impl<K: Codec + core::cmp::PartialOrd, V: Codec> RBMap<K, V> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<RBMap<K, V>, DecodeError> {
+        return RBMap::from_bytes_by(NaturalOrder, bytes);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_from<R: io::Read>(reader: R) -> Result<RBMap<K, V>, DecodeError> {
+        return RBMap::read_from_by(NaturalOrder, reader);
+    }
+}
+
+impl<K: Codec, S, C> RBTree<K, S, C> {
+    /* Returns: the set in the binary format, stored as a map to `()`. */
+    pub fn to_bytes(&self) -> Vec<u8> {
+        return self.map.to_bytes();
+    }
+
+    #[cfg(feature = "std")]
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        return self.map.write_to(writer);
+    }
+}
+
+impl<K: Codec, C: Comparator<K>> RBTree<K, (), C> {
+    pub fn from_bytes_by(cmp: C, bytes: &[u8]) -> Result<RBTree<K, (), C>, DecodeError> {
+        return Ok(RBTree {map: RBMap::from_bytes_by(cmp, bytes)?});
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_from_by<R: io::Read>(cmp: C, reader: R) -> Result<RBTree<K, (), C>, DecodeError> {
+        return Ok(RBTree {map: RBMap::read_from_by(cmp, reader)?});
+    }
+}
+
+impl<K: Codec + core::cmp::PartialOrd> RBTree<K> {
+    pub fn from_bytes(bytes: &[u8]) -> Result<RBTree<K>, DecodeError> {
+        return RBTree::from_bytes_by(NaturalOrder, bytes);
+    }
+
+    #[cfg(feature = "std")]
+    pub fn read_from<R: io::Read>(reader: R) -> Result<RBTree<K>, DecodeError> {
+        return RBTree::read_from_by(NaturalOrder, reader);
+    }
+}
+
+#[cfg(test)]
+mod fuzzer {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    type Value = (String, Vec<u16>, Option<char>);
+
+    fn random_map(max_len: usize) -> (RBMap<i32, Value>, BTreeMap<i32, Value>) {
+        let mut tree = RBMap::new();
+        let mut map = BTreeMap::new();
+        for _ in 0..rand::random::<usize>() % (max_len + 1) {
+            let key = rand::random::<i32>() % 1000;
+            let len = rand::random::<usize>() % 30;
+            let text = (0..len).map(|_| char::from_u32(rand::random::<u32>() % 0x3000).unwrap_or('?')).collect::<String>();
+            let numbers = (0..rand::random::<usize>() % 5).map(|_| rand::random::<u16>()).collect::<Vec<_>>();
+            let value = (text, numbers, rand::random::<bool>().then_some('x'));
+            tree.insert(key, value.clone());
+            map.insert(key, value);
+        }
+        return (tree, map);
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(crc32(b"123456789"), 0xcbf43926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for _ in 0..100 {
+            let (tree, map) = random_map(200);
+            let bytes = tree.to_bytes();
+            let loaded = RBMap::<i32, Value>::from_bytes(&bytes).unwrap();
+            assert!(loaded.is_rb_tree());
+            assert!(loaded.iter().eq(map.iter()));
+        }
+
+        let descending = |a: &u64, b: &u64| b.cmp(a);
+        let tree = RBTree::from_sorted_iter_by(descending, (0..1000).rev());
+        let loaded = RBTree::from_bytes_by(descending, &tree.to_bytes()).unwrap();
+        assert!(loaded.iter().eq(tree.iter()));
+        /* Loading in a different order than the one saved is caught. */
+        assert!(matches!(RBTree::<u64>::from_bytes(&tree.to_bytes()), Err(DecodeError::Unsorted)));
+    }
+
+    #[test]
+    fn test_corruption() {
+        for _ in 0..10 {
+            let (tree, _) = random_map(20);
+            let bytes = tree.to_bytes();
+            for len in 0..bytes.len() {
+                assert!(RBMap::<i32, Value>::from_bytes(&bytes[..len]).is_err());
+            }
+            for i in 0..bytes.len() {
+                let mut corrupt = bytes.clone();
+                corrupt[i] ^= 1 << (rand::random::<u32>() % 8);
+                assert!(RBMap::<i32, Value>::from_bytes(&corrupt).is_err());
+            }
+        }
+
+        let bytes = RBTree::from_sorted_iter(0u8..10).to_bytes();
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert!(matches!(RBTree::<u8>::from_bytes(&wrong_version), Err(DecodeError::UnsupportedVersion(2))));
+        assert!(matches!(RBTree::<u8>::from_bytes(b"not a tree at all"), Err(DecodeError::BadMagic)));
+        assert!(matches!(RBTree::<u8>::from_bytes(&bytes[..bytes.len() - 1]), Err(DecodeError::Truncated)));
+        /* A well-formed file whose keys are out of order is still rejected. */
+        let mut unsorted = bytes[..HEADER_LEN].to_vec();
+        unsorted.extend_from_slice(&[0, 1, 2, 3, 4, 5, 6, 7, 9, 8]);
+        let checksum = crc32(&unsorted);
+        checksum.encode(&mut unsorted);
+        assert!(matches!(RBTree::<u8>::from_bytes(&unsorted), Err(DecodeError::Unsorted)));
+    }
+}
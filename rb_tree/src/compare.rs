@@ -0,0 +1,30 @@
+use core::cmp::Ordering;
+
+/* The order an `RBMap` or `RBTree` keeps its keys in. Every comparison the
+ * tree makes goes through `compare`, so keys do not need to implement
+ * `PartialOrd` themselves. Any `Fn(&K, &K) -> Ordering` closure or function is
+ * a comparator. */
+pub trait Comparator<K> {
+    fn compare(&self, a: &K, b: &K) -> Ordering;
+}
+
+/* The order given by the keys' own `PartialOrd`. This is the default, and
+ * what `new` and `new_augmented` use. Keys that do not compare (like NaN)
+ * order after everything else, as they always have. */
+#[derive(Clone, Copy, Debug, Default)]
+pub struct NaturalOrder;
+
+impl<K: core::cmp::PartialOrd> Comparator<K> for NaturalOrder {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        if a < b {
+            return Ordering::Less;
+        }
+        return if a == b {Ordering::Equal} else {Ordering::Greater};
+    }
+}
+
+impl<K, F: Fn(&K, &K) -> Ordering> Comparator<K> for F {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        return self(a, b);
+    }
+}
@@ -0,0 +1,187 @@
+use std::sync::{Mutex, PoisonError, RwLock};
+
+use crate::PersistentRBTree;
+
+/* An ordered set that any number of threads can read and write through
+ * `&self`. It publishes immutable versions of a `PersistentRBTree`. Readers
+ * clone the current version, which holds the version lock only for a
+ * reference count bump, and then query it with no lock held. Writers are
+ * serialized by a separate mutex and build the next version while readers
+ * carry on; they take the version lock only to swap the new version in.
+ *
+ * Every published version is complete, so a writer that panics leaves the
+ * last version in place and the poisoned locks are safe to keep using. */
+pub struct ConcurrentRBTree<K: std::cmp::PartialOrd + Clone> {
+    current: RwLock<PersistentRBTree<K>>,
+    writer: Mutex<()>
+}
+
+impl<K: std::cmp::PartialOrd + Clone> Default for ConcurrentRBTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: std::cmp::PartialOrd + Clone> ConcurrentRBTree<K> {
+    pub fn new() -> ConcurrentRBTree<K> {
+        ConcurrentRBTree {current: RwLock::new(PersistentRBTree::new()), writer: Mutex::new(())}
+    }
+
+    /* Returns: the current version. Later writes do not affect it, so it can
+     * be iterated or queried repeatedly with a consistent view. */
+    pub fn snapshot(&self) -> PersistentRBTree<K> {
+        return self.current.read().unwrap_or_else(PoisonError::into_inner).clone();
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return self.snapshot().contains(key);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.snapshot().len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.snapshot().is_empty();
+    }
+
+    /* Returns: whether `key` was newly inserted. */
+    pub fn insert(&self, key: K) -> bool {
+        let (before, after) = self.update(|tree| tree.insert(key));
+        return after.len() > before.len();
+    }
+
+    /* Returns: whether `key` was present. */
+    pub fn remove(&self, key: &K) -> bool {
+        let (before, after) = self.update(|tree| tree.remove(key));
+        return after.len() < before.len();
+    }
+
+    /* Publishes `f(current version)` as the new version, with no other write
+     * in between, so several changes can be made atomically. Returns: the
+     * versions before and after. */
+    pub fn update<F: FnOnce(&PersistentRBTree<K>) -> PersistentRBTree<K>>(&self, f: F) -> (PersistentRBTree<K>, PersistentRBTree<K>) {
+        let _writer = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+        let before = self.snapshot();
+        let after = f(&before);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = after.clone();
+        return (before, after);
+    }
+}
+
+#[cfg(test)]
+mod stress {
+    use super::*;
+    use crate::{RBMap, RBTree};
+    use std::collections::BTreeSet;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+
+    fn assert_send_sync<T: Send + Sync>() {}
+
+    #[test]
+    fn test_send_sync() {
+        assert_send_sync::<RBTree<u32>>();
+        assert_send_sync::<RBMap<u32, String>>();
+        assert_send_sync::<PersistentRBTree<u32>>();
+        assert_send_sync::<ConcurrentRBTree<u32>>();
+    }
+
+    #[test]
+    fn test_parallel_reads() {
+        let mut tree = RBTree::<u32>::new();
+        for _ in 0..10000 {
+            tree.insert(rand::random::<u32>() % 100000);
+        }
+        let expected = tree.iter().copied().collect::<Vec<_>>();
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    assert!(tree.is_rb_tree());
+                    assert!(tree.iter().eq(expected.iter()));
+                    for _ in 0..10000 {
+                        let key = rand::random::<u32>() % 100000;
+                        assert_eq!(tree.contains(&key), expected.binary_search(&key).is_ok());
+                    }
+                });
+            }
+        });
+        /* A tree built on one thread can be moved to and modified on another. */
+        let tree = thread::spawn(move || {
+            tree.insert(100000);
+            tree
+        }).join().unwrap();
+        assert_eq!(tree.len(), expected.len() + 1);
+    }
+
+    #[test]
+    fn test_concurrent_writers() {
+        const WRITERS: u32 = 4;
+        let tree = ConcurrentRBTree::<u32>::new();
+        let done = AtomicBool::new(false);
+        let sets = thread::scope(|scope| {
+            /* Readers check every version they see while the writers run. */
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    while !done.load(Ordering::Relaxed) {
+                        let snapshot = tree.snapshot();
+                        assert!(snapshot.is_rb_tree());
+                        assert_eq!(snapshot.iter().count(), snapshot.len());
+                        assert!(snapshot.iter().zip(snapshot.iter().skip(1)).all(|(a, b)| a < b));
+                    }
+                });
+            }
+            /* Each writer owns the keys congruent to its index, so it can
+             * track exactly which of them should be present. */
+            let writers = (0..WRITERS).map(|w| {
+                let tree = &tree;
+                scope.spawn(move || {
+                    let mut set = BTreeSet::new();
+                    for _ in 0..2000 {
+                        let key = (rand::random::<u32>() % 500) * WRITERS + w;
+                        if rand::random::<u32>().is_multiple_of(3) {
+                            assert_eq!(tree.remove(&key), set.remove(&key));
+                        } else {
+                            assert_eq!(tree.insert(key), set.insert(key));
+                        }
+                        assert_eq!(tree.contains(&key), set.contains(&key));
+                    }
+                    set
+                })
+            }).collect::<Vec<_>>();
+            let sets = writers.into_iter().map(|writer| writer.join().unwrap()).collect::<Vec<_>>();
+            done.store(true, Ordering::Relaxed);
+            sets
+        });
+        let expected = sets.into_iter().flatten().collect::<BTreeSet<_>>();
+        let snapshot = tree.snapshot();
+        assert!(snapshot.is_rb_tree());
+        assert!(snapshot.iter().eq(expected.iter()));
+    }
+
+    #[test]
+    fn test_versions_are_linear() {
+        /* One writer appends 0, 1, 2, ...; every snapshot must hold exactly a
+         * prefix of that sequence, and no reader may see it shrink. */
+        let tree = ConcurrentRBTree::<u32>::new();
+        thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    let mut seen = 0;
+                    while seen < 3000 {
+                        let snapshot = tree.snapshot();
+                        assert!(snapshot.len() >= seen);
+                        seen = snapshot.len();
+                        assert!(snapshot.iter().copied().eq(0..seen as u32));
+                    }
+                });
+            }
+            scope.spawn(|| {
+                for i in 0..3000 {
+                    tree.update(|version| version.insert(i));
+                }
+            });
+        });
+        assert_eq!(tree.len(), 3000);
+    }
+}
@@ -0,0 +1,342 @@
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::ops::Bound;
+
+use crate::{Comparator, Gap, NaturalOrder, RBMap, RBNode, RBTree, Summary};
+
+/* A read-only position in an `RBMap`. A cursor points at an entry or at the
+ * "ghost" position, which sits past both ends: moving forward from the last
+ * entry reaches the ghost, and moving forward again reaches the first entry.
+ * Each step follows parent and child links, so stepping through k entries
+ * costs O(k + log n) with no descent from the root. */
+pub struct Cursor<'a, K, V, S = (), C = NaturalOrder> {
+    map: &'a RBMap<K, V, S, C>,
+    node: RBNode<K, V, S>
+}
+
+/* A cursor that can also remove the entry it points at and insert entries
+ * next to it. Insertions attach the new node directly beside the cursor, so
+ * they only pay for rebalancing. Sizes and summaries left stale by `insert`
+ * are settled when the cursor is dropped. A cursor leaked with `mem::forget`
+ * leaves the map correct but slower to read, until its next change settles
+ * them. */
+pub struct CursorMut<'a, K, V, S = (), C = NaturalOrder> {
+    map: &'a mut RBMap<K, V, S, C>,
+    node: RBNode<K, V, S>,
+    around: Option<Gap<K, V, S>>, /* the neighbours of `node`, when known */
+    pending: Option<Settle<K, V, S, C>> /* settles the map after `insert` */
+}
+
+type Settle<K, V, S, C> = fn(&mut RBMap<K, V, S, C>);
+
+impl<K, V, S, C> Clone for Cursor<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        Cursor {map: self.map, node: self.node}
+    }
+}
+
+impl<K, V, S, C> Drop for CursorMut<'_, K, V, S, C> {
+    fn drop(&mut self) {
+        if let Some(settle) = self.pending.take() {
+            settle(self.map);
+        }
+    }
+}
+
+impl<K, V, S, C> RBMap<K, V, S, C> {
+    fn cursor_mut_at(&mut self, node: RBNode<K, V, S>) -> CursorMut<'_, K, V, S, C> {
+        return CursorMut {map: self, node, around: None, pending: None};
+    }
+
+    /* Returns: the node after `x`, where None is the ghost position. */
+    fn next_node(&self, x: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        match x {
+            None => self.get_minimum(self.root),
+            Some(_) => self.get_successor(x)
+        }
+    }
+
+    /* Returns: the node before `x`, where None is the ghost position. */
+    fn prev_node(&self, x: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        match x {
+            None => self.get_maximum(self.root),
+            Some(_) => self.get_predecessor(x)
+        }
+    }
+
+    /* Returns: a cursor at the smallest key, or at the ghost if the map is empty. */
+    pub fn cursor_front(&self) -> Cursor<'_, K, V, S, C> {
+        return Cursor {map: self, node: self.get_minimum(self.root)};
+    }
+
+    /* Returns: a cursor at the largest key, or at the ghost if the map is empty. */
+    pub fn cursor_back(&self) -> Cursor<'_, K, V, S, C> {
+        return Cursor {map: self, node: self.get_maximum(self.root)};
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, K, V, S, C> {
+        return self.cursor_mut_at(self.get_minimum(self.root));
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, K, V, S, C> {
+        return self.cursor_mut_at(self.get_maximum(self.root));
+    }
+}
+
+impl<K, V, S, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Returns: a cursor at `key`, or None if it is missing. */
+    pub fn cursor_at(&self, key: &K) -> Option<Cursor<'_, K, V, S, C>> {
+        let node = self.lower_bound_node(Bound::Included(key))?;
+        if self.compare(&self.node(node).key, key) != Ordering::Equal {
+            return None;
+        }
+        return Some(Cursor {map: self, node: Some(node)});
+    }
+
+    /* Returns: a cursor at the smallest key that satisfies `bound` as a lower
+     * bound, or at the ghost if there is none. */
+    pub fn lower_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, V, S, C> {
+        return Cursor {map: self, node: self.lower_bound_node(bound)};
+    }
+
+    /* Returns: a cursor at the largest key that satisfies `bound` as an upper
+     * bound, or at the ghost if there is none. */
+    pub fn upper_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, V, S, C> {
+        return Cursor {map: self, node: self.upper_bound_node(bound)};
+    }
+
+    pub fn cursor_at_mut(&mut self, key: &K) -> Option<CursorMut<'_, K, V, S, C>> {
+        let node = self.cursor_at(key)?.node;
+        return Some(self.cursor_mut_at(node));
+    }
+
+    pub fn lower_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, V, S, C> {
+        return self.cursor_mut_at(self.lower_bound_node(bound));
+    }
+
+    pub fn upper_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, V, S, C> {
+        return self.cursor_mut_at(self.upper_bound_node(bound));
+    }
+}
+
+impl<'a, K, V, S, C> Cursor<'a, K, V, S, C> {
+    /* Returns: the entry at the cursor, or None at the ghost position. */
+    pub fn key_value(&self) -> Option<(&'a K, &'a V)> {
+        return self.node.map(|x| self.map.key_value(x));
+    }
+
+    pub fn key(&self) -> Option<&'a K> {
+        return self.key_value().map(|(key, _)| key);
+    }
+
+    pub fn value(&self) -> Option<&'a V> {
+        return self.key_value().map(|(_, value)| value);
+    }
+
+    pub fn move_next(&mut self) {
+        self.node = self.map.next_node(self.node);
+    }
+
+    pub fn move_prev(&mut self) {
+        self.node = self.map.prev_node(self.node);
+    }
+
+    /* Returns: the entry after the cursor, without moving it. */
+    pub fn peek_next(&self) -> Option<(&'a K, &'a V)> {
+        return self.map.next_node(self.node).map(|x| self.map.key_value(x));
+    }
+
+    /* Returns: the entry before the cursor, without moving it. */
+    pub fn peek_prev(&self) -> Option<(&'a K, &'a V)> {
+        return self.map.prev_node(self.node).map(|x| self.map.key_value(x));
+    }
+}
+
+impl<K, V, S, C> CursorMut<'_, K, V, S, C> {
+    /* Returns: a read-only cursor at the same position, for as long as this
+     * one is borrowed. */
+    pub fn as_cursor(&self) -> Cursor<'_, K, V, S, C> {
+        return Cursor {map: self.map, node: self.node};
+    }
+
+    pub fn key_value(&self) -> Option<(&K, &V)> {
+        return self.as_cursor().key_value();
+    }
+
+    pub fn key(&self) -> Option<&K> {
+        return self.as_cursor().key();
+    }
+
+    pub fn value(&self) -> Option<&V> {
+        return self.as_cursor().value();
+    }
+
+    pub fn move_next(&mut self) {
+        self.node = self.map.next_node(self.node);
+        self.around = None;
+    }
+
+    pub fn move_prev(&mut self) {
+        self.node = self.map.prev_node(self.node);
+        self.around = None;
+    }
+
+    pub fn peek_next(&self) -> Option<(&K, &V)> {
+        return self.as_cursor().peek_next();
+    }
+
+    pub fn peek_prev(&self) -> Option<(&K, &V)> {
+        return self.as_cursor().peek_prev();
+    }
+}
+
+impl<K, V, S, C: Comparator<K>> Cursor<'_, K, V, S, C> {
+    /* Moves the cursor to `key`, or to the smallest key above it (the ghost if
+     * there is none), searching outwards from the current entry. Seeking a
+     * nearby key is much cheaper than a lookup from the root; see
+     * `CursorMut::insert`. Returns: whether `key` is present. */
+    pub fn seek(&mut self, key: &K) -> bool {
+        let (node, found) = self.map.seek_from(self.node, key);
+        self.node = node;
+        return found;
+    }
+}
+
+impl<K, V, S, C: Comparator<K>> RBMap<K, V, S, C> {
+    fn seek_from(&self, hint: RBNode<K, V, S>, key: &K) -> (RBNode<K, V, S>, bool) {
+        match self.find_insert_position_from(hint, None, key) {
+            Ok(x) => return (Some(x), true),
+            Err((None, _)) => return (None, false),
+            Err((y, _)) => {
+                /* `key` would hang off `y`, so it sorts just before or just after it. */
+                let before = self.compare(key, &self.node(y.expect("INVALID STATE!")).key) == Ordering::Less;
+                return (if before {y} else {self.get_successor(y)}, false);
+            }
+        }
+    }
+}
+
+impl<K, V, S, C: Comparator<K>> CursorMut<'_, K, V, S, C> {
+    pub fn seek(&mut self, key: &K) -> bool {
+        let (node, found) = self.map.seek_from(self.node, key);
+        self.node = node;
+        self.around = None;
+        return found;
+    }
+}
+
+impl<K, V, C> CursorMut<'_, K, V, (), C> {
+    /* As with `RBMap::get_mut`, only maps without a summary hand out mutable
+     * values. */
+    pub fn value_mut(&mut self) -> Option<&mut V> {
+        let x = self.node?;
+        return Some(&mut self.map.node_mut(x).value);
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>> CursorMut<'_, K, V, S, C> {
+    /* Removes the entry at the cursor and moves to the next one. Returns: the
+     * removed entry, or None at the ghost position. */
+    pub fn remove_current(&mut self) -> Option<(K, V)> {
+        let x = self.node?;
+        self.around = None;
+        self.node = self.map.get_successor(self.node);
+        return Some(self.map.take_node(x));
+    }
+
+    /* Inserts `key` wherever it belongs and moves the cursor to it, as
+     * `RBMap::insert` would but searching outwards from the cursor instead of
+     * down from the root (from the root at the ghost position). A key next to
+     * the cursor is placed with two comparisons, and the cursor remembers the
+     * new entry's neighbours, so a next key that falls on either side of it,
+     * short of those neighbours, is placed without climbing the tree. Subtree
+     * sizes and summaries are only marked stale on the way up and settled all
+     * at once later, so feeding a sorted stream through one cursor costs O(1)
+     * amortized per insert. Returns: the value replaced when `key` was
+     * already present. */
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        let (x, old, around) = self.map.insert_from(self.node, self.around, key, value);
+        self.node = Some(x);
+        self.around = around;
+        self.pending = Some(RBMap::settle);
+        return old;
+    }
+
+    /* Inserts an entry just after the cursor, or at the front when the cursor
+     * is at the ghost position. The cursor does not move. Panics if `key`
+     * does not sort strictly between the keys around the new entry. */
+    pub fn insert_after(&mut self, key: K, value: V) {
+        let next = self.map.next_node(self.node);
+        self.insert_between(self.node, next, key, value);
+    }
+
+    /* Inserts an entry just before the cursor, or at the back when the cursor
+     * is at the ghost position. The cursor does not move. Panics if `key`
+     * does not sort strictly between the keys around the new entry. */
+    pub fn insert_before(&mut self, key: K, value: V) {
+        let prev = self.map.prev_node(self.node);
+        self.insert_between(prev, self.node, key, value);
+    }
+
+    /* `prev` and `next` are adjacent, so either `prev` has no right child or
+     * `next` is the minimum of that child and has no left child. */
+    fn insert_between(&mut self, prev: RBNode<K, V, S>, next: RBNode<K, V, S>, key: K, value: V) {
+        self.around = None;
+        let map = &*self.map;
+        assert!(
+            prev.is_none_or(|p| map.compare(&map.node(p).key, &key) == Ordering::Less)
+                && next.is_none_or(|n| map.compare(&key, &map.node(n).key) == Ordering::Less),
+            "cursor insert: key must sort strictly between its new neighbours"
+        );
+        if prev.is_some() && self.map.get_right(prev).is_none() {
+            self.map.attach(prev, false, key, value);
+        } else {
+            self.map.attach(next, true, key, value);
+        }
+    }
+}
+
+impl<K, S, C> RBTree<K, S, C> {
+    /* Set cursors walk `(key, &())` entries; insert through them with `()`. */
+    pub fn cursor_front(&self) -> Cursor<'_, K, (), S, C> {
+        return self.map.cursor_front();
+    }
+
+    pub fn cursor_back(&self) -> Cursor<'_, K, (), S, C> {
+        return self.map.cursor_back();
+    }
+
+    pub fn cursor_front_mut(&mut self) -> CursorMut<'_, K, (), S, C> {
+        return self.map.cursor_front_mut();
+    }
+
+    pub fn cursor_back_mut(&mut self) -> CursorMut<'_, K, (), S, C> {
+        return self.map.cursor_back_mut();
+    }
+}
+
+impl<K, S, C: Comparator<K>> RBTree<K, S, C> {
+    pub fn cursor_at(&self, key: &K) -> Option<Cursor<'_, K, (), S, C>> {
+        return self.map.cursor_at(key);
+    }
+
+    pub fn lower_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, (), S, C> {
+        return self.map.lower_bound(bound);
+    }
+
+    pub fn upper_bound(&self, bound: Bound<&K>) -> Cursor<'_, K, (), S, C> {
+        return self.map.upper_bound(bound);
+    }
+
+    pub fn cursor_at_mut(&mut self, key: &K) -> Option<CursorMut<'_, K, (), S, C>> {
+        return self.map.cursor_at_mut(key);
+    }
+
+    pub fn lower_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, (), S, C> {
+        return self.map.lower_bound_mut(bound);
+    }
+
+    pub fn upper_bound_mut(&mut self, bound: Bound<&K>) -> CursorMut<'_, K, (), S, C> {
+        return self.map.upper_bound_mut(bound);
+    }
+}
@@ -0,0 +1,121 @@
+use core::fmt::Debug;
+
+use crate::{Comparator, NaturalOrder, NodeId, RBMap, RBNode};
+
+/* A view into a single entry of an `RBMap`, as returned by `RBMap::entry`. */
+pub enum Entry<'a, K, V, C = NaturalOrder> {
+    Occupied(OccupiedEntry<'a, K, V, C>),
+    Vacant(VacantEntry<'a, K, V, C>)
+}
+
+/* An entry whose key is already in the map. */
+pub struct OccupiedEntry<'a, K, V, C = NaturalOrder> {
+    pub(crate) map: &'a mut RBMap<K, V, (), C>,
+    pub(crate) node: NodeId<K, V>
+}
+
+/* An entry whose key is missing. It remembers where the descent ended, so
+ * inserting only has to attach the node and rebalance. */
+pub struct VacantEntry<'a, K, V, C = NaturalOrder> {
+    pub(crate) map: &'a mut RBMap<K, V, (), C>,
+    pub(crate) key: K,
+    pub(crate) parent: RBNode<K, V>
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>> Entry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        match self {
+            Entry::Occupied(entry) => entry.key(),
+            Entry::Vacant(entry) => entry.key()
+        }
+    }
+
+    pub fn or_insert(self, default: V) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default)
+        }
+    }
+
+    pub fn or_insert_with<F: FnOnce() -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => entry.insert(default())
+        }
+    }
+
+    pub fn or_insert_with_key<F: FnOnce(&K) -> V>(self, default: F) -> &'a mut V {
+        match self {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let value = default(entry.key());
+                entry.insert(value)
+            }
+        }
+    }
+
+    /* Calls `f` on the value if the entry is occupied. */
+    pub fn and_modify<F: FnOnce(&mut V)>(self, f: F) -> Self {
+        match self {
+            Entry::Occupied(mut entry) => {
+                f(entry.get_mut());
+                Entry::Occupied(entry)
+            }
+            Entry::Vacant(entry) => Entry::Vacant(entry)
+        }
+    }
+}
+
+impl<'a, K: Debug, V: Default, C: Comparator<K>> Entry<'a, K, V, C> {
+    pub fn or_default(self) -> &'a mut V {
+        self.or_insert_with(V::default)
+    }
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>> OccupiedEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        return &self.map.node(self.node).key;
+    }
+
+    pub fn get(&self) -> &V {
+        return &self.map.node(self.node).value;
+    }
+
+    pub fn get_mut(&mut self) -> &mut V {
+        return &mut self.map.node_mut(self.node).value;
+    }
+
+    /* Returns: a reference to the value that lives as long as the map borrow. */
+    pub fn into_mut(self) -> &'a mut V {
+        return &mut self.map.node_mut(self.node).value;
+    }
+
+    /* Replaces the value and returns the old one. */
+    pub fn insert(&mut self, value: V) -> V {
+        return core::mem::replace(self.get_mut(), value);
+    }
+
+    pub fn remove(self) -> V {
+        return self.remove_entry().1;
+    }
+
+    pub fn remove_entry(self) -> (K, V) {
+        return self.map.take_node(self.node);
+    }
+}
+
+impl<'a, K: Debug, V, C: Comparator<K>> VacantEntry<'a, K, V, C> {
+    pub fn key(&self) -> &K {
+        return &self.key;
+    }
+
+    pub fn into_key(self) -> K {
+        return self.key;
+    }
+
+    /* Inserts the key with `value` and returns a reference to the value. */
+    pub fn insert(self, value: V) -> &'a mut V {
+        let node = self.map.insert_at(self.parent, self.key, value);
+        return &mut self.map.node_mut(node).value;
+    }
+}
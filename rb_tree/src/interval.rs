@@ -0,0 +1,441 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+use core::ops::Bound;
+
+use crate::{NodeId, RBMap, RBNode};
+
+/* A half-open interval `start..end`. Intervals order by start, then by end. */
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Interval<T> {
+    pub start: T,
+    pub end: T
+}
+
+impl<T> From<core::ops::Range<T>> for Interval<T> {
+    fn from(range: core::ops::Range<T>) -> Self {
+        Interval {start: range.start, end: range.end}
+    }
+}
+
+/* The value stored under an interval, plus the node's heap slot. */
+struct Item<T, V> {
+    value: V,
+    held: Option<Id<T, V>>, /* the node whose interval sits in this node's slot */
+    promoted: bool /* this node's own interval sits in a slot, here or above */
+}
+
+type Id<T, V> = NodeId<Interval<T>, Item<T, V>>;
+
+type Map<T, V> = RBMap<Interval<T>, Item<T, V>>;
+
+/* A map from half-open intervals to values that can report every interval
+ * overlapping a query in O(log n + k). It is a priority search tree laid over
+ * an `RBMap` keyed by interval, so ordered by start. Besides its own interval
+ * every node has a slot, which holds the interval reaching furthest among
+ * those under it that no slot above holds; an interval is either in the slot
+ * of its own node or of an ancestor, or else not in any slot. A query then
+ * stops at the first slot ending before it, and only follows the start order
+ * down one side. Inserts and removals cost O(log² n): after the map has
+ * rebalanced, the slots along the changed path are refilled. */
+pub struct IntervalTree<T: core::cmp::PartialOrd + Clone + Debug, V = ()> {
+    map: Map<T, V>
+}
+
+impl<T: core::cmp::PartialOrd + Clone + Debug, V> Default for IntervalTree<T, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: core::cmp::PartialOrd + Clone + Debug, V> IntervalTree<T, V> {
+    pub fn new() -> IntervalTree<T, V> {
+        IntervalTree {map: RBMap::new()}
+    }
+
+    /* Inserts `interval` with `value`. Returns: the previous value if this
+     * exact interval was already present. */
+    pub fn insert(&mut self, interval: core::ops::Range<T>, value: V) -> Option<V> {
+        let key = Interval::from(interval);
+        match self.map.find_insert_position(&key) {
+            Ok(x) => return Some(core::mem::replace(&mut self.item_mut(x).value, value)),
+            Err(y) => {
+                let z = self.map.insert_at(y, key, Item {value, held: None, promoted: false});
+                let changed = self.around_path(Some(z));
+                self.repair(changed, Vec::new());
+                return None;
+            }
+        }
+    }
+
+    /* Removes this exact interval. Other intervals overlapping it are kept. */
+    pub fn remove(&mut self, interval: &core::ops::Range<T>) -> Option<V> {
+        let z = self.map.find(&Interval::from(interval.clone()))?;
+
+        /* The lowest node the removal changes: where the successor of `z`
+         * leaves from when it takes the place of `z`, else the parent of `z`. */
+        let node = self.map.node(z);
+        let anchor = match (node.left, node.right) {
+            (Some(_), Some(r)) => {
+                let y = self.map.get_minimum(Some(r));
+                if y == Some(r) {y} else {self.map.get_parent(y)}
+            }
+            _ => node.p
+        };
+
+        /* Take the interval of `z` out of its slot, and the one `z` holds out
+         * of the heap until the tree is rebalanced. */
+        if self.item(z).promoted {
+            let mut x = Some(z);
+            while let Some(id) = x {
+                if self.item(id).held == Some(z) {
+                    self.item_mut(id).held = None;
+                    break;
+                }
+                x = self.map.get_parent(x);
+            }
+        }
+        let loose = Vec::from_iter(self.item_mut(z).held.take());
+        let (_, item) = self.map.take_node(z);
+        let changed = self.around_path(anchor);
+        self.repair(changed, loose);
+        return Some(item.value);
+    }
+
+    pub fn get(&self, interval: &core::ops::Range<T>) -> Option<&V> {
+        return self.map.get(&Interval::from(interval.clone())).map(|item| &item.value);
+    }
+
+    pub fn contains(&self, interval: &core::ops::Range<T>) -> bool {
+        return self.map.contains_key(&Interval::from(interval.clone()));
+    }
+
+    pub fn len(&self) -> usize {
+        return self.map.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.map.is_empty();
+    }
+
+    /* Returns: every interval in order of start. */
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&Interval<T>, &V)> + ExactSizeIterator {
+        return self.map.iter().map(|(interval, item)| (interval, &item.value));
+    }
+
+    /* Returns: the intervals that share at least one point with `query`, in
+     * no particular order. A query with k matches costs O(log n + k). */
+    pub fn overlapping(&self, query: core::ops::Range<T>) -> Overlapping<'_, T, V> {
+        return Overlapping::new(&self.map, query.start, Bound::Excluded(query.end));
+    }
+
+    /* Returns: the intervals that contain `point`, in no particular order. */
+    pub fn containing(&self, point: &T) -> Overlapping<'_, T, V> {
+        return Overlapping::new(&self.map, point.clone(), Bound::Included(point.clone()));
+    }
+
+    fn item(&self, x: Id<T, V>) -> &Item<T, V> {
+        return &self.map.node(x).value;
+    }
+
+    fn item_mut(&mut self, x: Id<T, V>) -> &mut Item<T, V> {
+        return &mut self.map.node_mut(x).value;
+    }
+
+    fn end(&self, x: Id<T, V>) -> &T {
+        return &self.map.node(x).key.end;
+    }
+
+    /* Returns: `x`, its ancestors and their other children, each listed after
+     * its children. Rebalancing after an insert or removal at `x` only moves
+     * these nodes, and every other subtree keeps its nodes. */
+    fn around_path(&self, mut x: RBNode<Interval<T>, Item<T, V>>) -> Vec<Id<T, V>> {
+        let mut nodes = Vec::new();
+        let mut below = None;
+        while let Some(id) = x {
+            let node = self.map.node(id);
+            for child in [node.left, node.right].into_iter().flatten() {
+                if Some(child) != below {
+                    nodes.push(child);
+                }
+            }
+            nodes.push(id);
+            below = x;
+            x = node.p;
+        }
+        return nodes;
+    }
+
+    /* Refills the slots of the `changed` nodes, whose subtrees no longer hold
+     * what their slots were filled from, and puts back the `loose` intervals,
+     * which are in no slot yet still promoted. */
+    fn repair(&mut self, changed: Vec<Id<T, V>>, mut loose: Vec<Id<T, V>>) {
+        for &x in &changed {
+            loose.extend(self.item_mut(x).held.take());
+        }
+        let mut sorted = changed.iter().map(|x| x.index()).collect::<Vec<_>>();
+        sorted.sort_unstable();
+
+        /* The intervals of changed nodes return to their nodes, to be lifted
+         * from there. The others belong to untouched subtrees and are sunk
+         * into them from the root once the changed slots are full again. */
+        let mut sink = Vec::new();
+        for x in loose {
+            if sorted.binary_search(&x.index()).is_ok() {
+                self.item_mut(x).promoted = false;
+            } else {
+                sink.push(x);
+            }
+        }
+        for &x in &changed {
+            self.lift(x);
+        }
+        for x in sink {
+            self.sink(x);
+        }
+    }
+
+    /* Fills the empty slot of `x`, whose children's slots are in order, with
+     * the interval reaching furthest among its own (if not promoted) and
+     * those in its children's slots, then fills whichever slot that emptied
+     * in turn. Costs O(height of `x`). */
+    fn lift(&mut self, mut x: Id<T, V>) {
+        loop {
+            let node = self.map.node(x);
+            let mut best = if node.value.promoted {None} else {Some(x)};
+            let mut from = None;
+            for child in [node.left, node.right].into_iter().flatten() {
+                if let Some(held) = self.item(child).held {
+                    if best.is_none_or(|best| self.end(best) < self.end(held)) {
+                        best = Some(held);
+                        from = Some(child);
+                    }
+                }
+            }
+            let Some(best) = best else {
+                return;
+            };
+            self.item_mut(x).held = Some(best);
+            self.item_mut(best).promoted = true;
+            let Some(child) = from else {
+                return;
+            };
+            self.item_mut(child).held = None;
+            x = child;
+        }
+    }
+
+    /* Puts the loose interval of node `p` back into the heap, walking down
+     * from the root towards `p` and swapping it into the first slot that is
+     * empty or holds an interval ending before it. The interval swapped out
+     * carries on down the same way. Costs O(log n). */
+    fn sink(&mut self, mut p: Id<T, V>) {
+        let mut x = self.map.root.expect("INVALID STATE!");
+        loop {
+            match self.item(x).held {
+                None => {
+                    self.item_mut(x).held = Some(p);
+                    return;
+                }
+                Some(held) => {
+                    if self.end(held) < self.end(p) {
+                        self.item_mut(x).held = Some(p);
+                        p = held;
+                    }
+                }
+            }
+            if p == x {
+                self.item_mut(x).promoted = false;
+                return;
+            }
+            let node = self.map.node(x);
+            let goes_left = self.map.compare(&self.map.node(p).key, &node.key) == Ordering::Less;
+            x = if goes_left {node.left} else {node.right}.expect("INVALID STATE!");
+        }
+    }
+
+    /* Checks that every slot holds an interval from its own subtree, held
+     * nowhere else, that reaches at least as far as every interval below it
+     * that is not promoted past it, and that a slot is only empty when
+     * nothing below it is left to fill it. */
+    #[allow(dead_code)]
+    fn is_heap(&self) -> bool {
+        let mut held = Vec::new();
+        let mut promoted = 0;
+        let mut stack = Vec::from_iter(self.map.root);
+        while let Some(x) = stack.pop() {
+            let node = self.map.node(x);
+            stack.extend(node.left);
+            stack.extend(node.right);
+            promoted += node.value.promoted as usize;
+            let below = [node.left, node.right].into_iter().flatten().filter_map(|child| self.item(child).held);
+            let below = below.chain((!node.value.promoted).then_some(x)).collect::<Vec<_>>();
+            let Some(h) = node.value.held else {
+                if !below.is_empty() {
+                    return false;
+                }
+                continue;
+            };
+            if !self.item(h).promoted || below.iter().any(|&b| self.end(h) < self.end(b)) {
+                return false;
+            }
+            let mut up = Some(h);
+            while up.is_some_and(|up| up != x) {
+                up = self.map.get_parent(up);
+            }
+            if up.is_none() {
+                return false;
+            }
+            held.push(h.index());
+        }
+        held.sort_unstable();
+        held.dedup();
+        return held.len() == promoted;
+    }
+}
+
+/* Walk over the nodes whose slots reach past `after`. A node whose slot ends
+ * at or before `after` has nothing under it that does either, and a right
+ * subtree is only entered when its parent starts before `before`, so apart
+ * from one path along `before` every node entered reports its slot. */
+pub struct Overlapping<'a, T: core::cmp::PartialOrd + Clone, V> {
+    map: &'a Map<T, V>,
+    stack: Vec<Id<T, V>>,
+    own: Option<Id<T, V>>, /* a node whose own interval is due next */
+    after: T,
+    before: Bound<T>,
+    #[cfg(test)]
+    visited: usize /* nodes examined so far */
+}
+
+impl<'a, T: core::cmp::PartialOrd + Clone, V> Overlapping<'a, T, V> {
+    fn new(map: &'a Map<T, V>, after: T, before: Bound<T>) -> Self {
+        return Overlapping {
+            map,
+            stack: Vec::from_iter(map.root),
+            own: None,
+            after,
+            before,
+            #[cfg(test)]
+            visited: 0
+        };
+    }
+
+    fn starts_in(&self, x: Id<T, V>) -> bool {
+        let start = &self.map.node(x).key.start;
+        match &self.before {
+            Bound::Included(b) => start <= b,
+            Bound::Excluded(b) => start < b,
+            Bound::Unbounded => true
+        }
+    }
+
+    fn entry(&self, x: Id<T, V>) -> (&'a Interval<T>, &'a V) {
+        let (interval, item) = self.map.key_value(x);
+        return (interval, &item.value);
+    }
+}
+
+impl<'a, T: core::cmp::PartialOrd + Clone, V> Iterator for Overlapping<'a, T, V> {
+    type Item = (&'a Interval<T>, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(x) = self.own.take() {
+            return Some(self.entry(x));
+        }
+        while let Some(x) = self.stack.pop() {
+            #[cfg(test)]
+            {
+                self.visited += 1;
+            }
+            let node = self.map.node(x);
+            let Some(held) = node.value.held else {
+                continue;
+            };
+            if self.map.node(held).key.end <= self.after {
+                continue;
+            }
+            if self.starts_in(x) {
+                self.stack.extend(node.right);
+                if !node.value.promoted && node.key.end > self.after {
+                    self.own = Some(x);
+                }
+            }
+            self.stack.extend(node.left);
+            if self.starts_in(held) {
+                return Some(self.entry(held));
+            }
+            if let Some(x) = self.own.take() {
+                return Some(self.entry(x));
+            }
+        }
+        return None;
+    }
+}
+
+#[cfg(test)]
+mod fuzzer {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_interval_tree() {
+        for _ in 0..20 {
+            let mut tree = IntervalTree::<u16, u32>::new();
+            let mut intervals = BTreeMap::<(u16, u16), u32>::new();
+            for i in 0..500 {
+                let start = rand::random::<u16>() % 1000;
+                let end = start + rand::random::<u16>() % 50;
+                if rand::random::<u32>().is_multiple_of(4) && !intervals.is_empty() {
+                    let k = rand::random::<usize>() % intervals.len();
+                    let (&(s, e), _) = intervals.iter().nth(k).unwrap();
+                    assert_eq!(tree.remove(&(s..e)), intervals.remove(&(s, e)));
+                } else {
+                    assert_eq!(tree.insert(start..end, i), intervals.insert((start, end), i));
+                }
+                assert!(tree.map.is_rb_tree(), "tree is not rb tree{}", {tree.map.print(); ""});
+                assert!(tree.is_heap());
+                assert_eq!(tree.len(), intervals.len());
+
+                let q_start = rand::random::<u16>() % 1000;
+                let q_end = q_start + rand::random::<u16>() % 100;
+                let mut found = tree.overlapping(q_start..q_end)
+                    .map(|(iv, v)| ((iv.start, iv.end), *v))
+                    .collect::<Vec<_>>();
+                found.sort();
+                let expected = intervals.iter()
+                    .filter(|((s, e), _)| *s < q_end && q_start < *e)
+                    .map(|(k, v)| (*k, *v))
+                    .collect::<Vec<_>>();
+                assert_eq!(found, expected);
+                let mut found = tree.containing(&q_start).map(|(iv, _)| (iv.start, iv.end)).collect::<Vec<_>>();
+                found.sort();
+                let expected = intervals.keys()
+                    .filter(|(s, e)| *s <= q_start && q_start < *e)
+                    .copied()
+                    .collect::<Vec<_>>();
+                assert_eq!(found, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_scattered_overlaps() {
+        /* Unit intervals, every 64th of which reaches past the query: the k
+         * matches are spread over the whole tree, yet sit in the top slots. */
+        let n = 4096;
+        let mut tree = IntervalTree::<u32>::new();
+        for i in 0..n {
+            tree.insert(i..if i % 64 == 0 {2 * n} else {i + 1}, ());
+        }
+        let mut query = tree.overlapping(n..n + 1);
+        assert_eq!(query.by_ref().count(), 64);
+        let (k, log_n) = (64, n.ilog2() as usize);
+        assert!(query.visited <= 2 * (k + 2 * log_n), "visited {} nodes", query.visited);
+
+        /* 100 of them start in the query, plus the long intervals from 0 and 64. */
+        let mut query = tree.overlapping(100..200);
+        assert_eq!(query.by_ref().count(), 102);
+        assert!(query.visited <= 2 * (102 + 2 * log_n), "visited {} nodes", query.visited);
+    }
+}
@@ -0,0 +1,333 @@
+use core::fmt::Debug;
+use core::iter::FusedIterator;
+
+use crate::{Comparator, NaturalOrder, RBMap, RBNode, RBTree, Summary};
+
+/* In-order iterator over the entries of an `RBMap`. The two ends walk towards
+ * each other through the parent links; `remaining` tells them when they meet. */
+pub struct Iter<'a, K, V, S = (), C = NaturalOrder> {
+    map: &'a RBMap<K, V, S, C>,
+    front: RBNode<K, V, S>,
+    back: RBNode<K, V, S>,
+    remaining: usize
+}
+
+impl<'a, K, V, S, C> Iter<'a, K, V, S, C> {
+    pub(crate) fn new(map: &'a RBMap<K, V, S, C>, front: RBNode<K, V, S>, back: RBNode<K, V, S>, remaining: usize) -> Self {
+        Iter {map, front, back, remaining}
+    }
+}
+
+impl<K, V, S, C> Clone for Iter<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        Iter {map: self.map, front: self.front, back: self.back, remaining: self.remaining}
+    }
+}
+
+impl<'a, K, V, S, C> Iterator for Iter<'a, K, V, S, C> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.front.expect("INVALID STATE!");
+        self.front = self.map.get_successor(self.front);
+        return Some(self.map.key_value(node));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S, C> DoubleEndedIterator for Iter<'_, K, V, S, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        let node = self.back.expect("INVALID STATE!");
+        self.back = self.map.get_predecessor(self.back);
+        return Some(self.map.key_value(node));
+    }
+}
+
+impl<K, V, S, C> ExactSizeIterator for Iter<'_, K, V, S, C> {}
+impl<K, V, S, C> FusedIterator for Iter<'_, K, V, S, C> {}
+
+/* In-order iterator over the entries of an `RBMap` between two nodes, both
+ * inclusive. The size is not known up front, so the ends stop once they have
+ * crossed the same node. */
+pub struct Range<'a, K, V, S = (), C = NaturalOrder> {
+    map: &'a RBMap<K, V, S, C>,
+    front: RBNode<K, V, S>,
+    back: RBNode<K, V, S>,
+    finished: bool
+}
+
+impl<'a, K, V, S, C> Range<'a, K, V, S, C> {
+    /* `finished` must be set when `front` comes after `back`, which takes a
+     * key comparison that only the caller can make. */
+    pub(crate) fn new(map: &'a RBMap<K, V, S, C>, front: RBNode<K, V, S>, back: RBNode<K, V, S>, finished: bool) -> Self {
+        Range {map, front, back, finished}
+    }
+}
+
+impl<K, V, S, C> Clone for Range<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        Range {map: self.map, front: self.front, back: self.back, finished: self.finished}
+    }
+}
+
+impl<'a, K, V, S, C> Iterator for Range<'a, K, V, S, C> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let node = self.front.expect("INVALID STATE!");
+        if self.front == self.back {
+            self.finished = true;
+        } else {
+            self.front = self.map.get_successor(self.front);
+        }
+        return Some(self.map.key_value(node));
+    }
+}
+
+impl<K, V, S, C> DoubleEndedIterator for Range<'_, K, V, S, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let node = self.back.expect("INVALID STATE!");
+        if self.back == self.front {
+            self.finished = true;
+        } else {
+            self.back = self.map.get_predecessor(self.back);
+        }
+        return Some(self.map.key_value(node));
+    }
+}
+
+impl<K, V, S, C> FusedIterator for Range<'_, K, V, S, C> {}
+
+/* Consuming in-order iterator. Each step unlinks the current minimum (or
+ * maximum) by splicing its only child into its place, so the remaining nodes
+ * always form a search tree and the whole walk costs O(n). Colors, sizes and
+ * summaries are not maintained since the tree is being torn down. */
+pub struct IntoIter<K, V, S = (), C = NaturalOrder> {
+    map: RBMap<K, V, S, C>,
+    front: RBNode<K, V, S>,
+    back: RBNode<K, V, S>,
+    remaining: usize
+}
+
+impl<K, V, S, C> IntoIter<K, V, S, C> {
+    fn take(&mut self, x: RBNode<K, V, S>) -> (K, V) {
+        let node = self.map.deallocate(x.expect("INVALID STATE!"));
+        return (node.key, node.value);
+    }
+}
+
+impl<K, V, S, C> Iterator for IntoIter<K, V, S, C> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        /* x is the minimum, so it has no left child and is a left child itself. */
+        let x = self.front;
+        let x_parent = self.map.get_parent(x);
+        let x_right = self.map.get_right(x);
+        self.map.set_parent(x_right, x_parent);
+        if x_parent.is_none() {
+            self.map.root = x_right;
+        } else {
+            self.map.set_left(x_parent, x_right);
+        }
+        self.front = if x_right.is_some() {
+            self.map.get_minimum(x_right)
+        } else {
+            x_parent
+        };
+        return Some(self.take(x));
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K, V, S, C> DoubleEndedIterator for IntoIter<K, V, S, C> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+        /* x is the maximum, so it has no right child and is a right child itself. */
+        let x = self.back;
+        let x_parent = self.map.get_parent(x);
+        let x_left = self.map.get_left(x);
+        self.map.set_parent(x_left, x_parent);
+        if x_parent.is_none() {
+            self.map.root = x_left;
+        } else {
+            self.map.set_right(x_parent, x_left);
+        }
+        self.back = if x_left.is_some() {
+            self.map.get_maximum(x_left)
+        } else {
+            x_parent
+        };
+        return Some(self.take(x));
+    }
+}
+
+impl<K, V, S, C> ExactSizeIterator for IntoIter<K, V, S, C> {}
+impl<K, V, S, C> FusedIterator for IntoIter<K, V, S, C> {}
+
+impl<K, V, S, C> IntoIterator for RBMap<K, V, S, C> {
+    type Item = (K, V);
+    type IntoIter = IntoIter<K, V, S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let front = self.get_minimum(self.root);
+        let back = self.get_maximum(self.root);
+        let remaining = self.len;
+        IntoIter {map: self, front, back, remaining}
+    }
+}
+
+impl<'a, K, V, S, C> IntoIterator for &'a RBMap<K, V, S, C> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V, S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K, S, C> IntoIterator for RBTree<K, S, C> {
+    type Item = K;
+    type IntoIter = IntoKeys<K, (), S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_keys()
+    }
+}
+
+impl<'a, K, S, C> IntoIterator for &'a RBTree<K, S, C> {
+    type Item = &'a K;
+    type IntoIter = Keys<'a, K, (), S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.keys()
+    }
+}
+
+macro_rules! projection_iter {
+    ($name:ident, $inner:ident, $item:ty, $field:tt $(, $lt:lifetime)?) => {
+        pub struct $name<$($lt,)? K, V, S = (), C = NaturalOrder> {
+            pub(crate) inner: $inner<$($lt,)? K, V, S, C>
+        }
+
+        impl<$($lt,)? K, V, S, C> Iterator for $name<$($lt,)? K, V, S, C> {
+            type Item = $item;
+
+            fn next(&mut self) -> Option<Self::Item> {
+                self.inner.next().map(|entry| entry.$field)
+            }
+
+            fn size_hint(&self) -> (usize, Option<usize>) {
+                self.inner.size_hint()
+            }
+        }
+
+        impl<$($lt,)? K, V, S, C> DoubleEndedIterator for $name<$($lt,)? K, V, S, C> {
+            fn next_back(&mut self) -> Option<Self::Item> {
+                self.inner.next_back().map(|entry| entry.$field)
+            }
+        }
+
+        impl<$($lt,)? K, V, S, C> FusedIterator for $name<$($lt,)? K, V, S, C> {}
+    };
+}
+
+projection_iter!(Keys, Iter, &'a K, 0, 'a);
+projection_iter!(Values, Iter, &'a V, 1, 'a);
+projection_iter!(IntoKeys, IntoIter, K, 0);
+projection_iter!(IntoValues, IntoIter, V, 1);
+projection_iter!(RangeKeys, Range, &'a K, 0, 'a);
+
+impl<K, V, S, C> ExactSizeIterator for Keys<'_, K, V, S, C> {}
+impl<K, V, S, C> ExactSizeIterator for Values<'_, K, V, S, C> {}
+impl<K, V, S, C> ExactSizeIterator for IntoKeys<K, V, S, C> {}
+impl<K, V, S, C> ExactSizeIterator for IntoValues<K, V, S, C> {}
+
+impl<K, V, S, C> Clone for Keys<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        Keys {inner: self.inner.clone()}
+    }
+}
+
+impl<K, V, S, C> Clone for Values<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        Values {inner: self.inner.clone()}
+    }
+}
+
+impl<K, V, S, C> Clone for RangeKeys<'_, K, V, S, C> {
+    fn clone(&self) -> Self {
+        RangeKeys {inner: self.inner.clone()}
+    }
+}
+
+/* Removes and yields the entries of an `RBMap` that match a predicate. See
+ * `RBMap::extract_if`. */
+pub struct ExtractIf<'a, K, V, S, C, F> {
+    pub(crate) map: &'a mut RBMap<K, V, S, C>,
+    pub(crate) next: RBNode<K, V, S>,
+    pub(crate) pred: F
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>, F: FnMut(&K, &V) -> bool> Iterator for ExtractIf<'_, K, V, S, C, F> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        return self.map.extract_next(&mut self.next, &mut self.pred);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.len()))
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>, F: FnMut(&K, &V) -> bool> FusedIterator for ExtractIf<'_, K, V, S, C, F> {}
+
+/* Removes and yields the keys of an `RBTree` that match a predicate. */
+pub struct ExtractKeysIf<'a, K, S, C, F> {
+    pub(crate) map: &'a mut RBMap<K, (), S, C>,
+    pub(crate) next: RBNode<K, (), S>,
+    pub(crate) pred: F
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>, F: FnMut(&K) -> bool> Iterator for ExtractKeysIf<'_, K, S, C, F> {
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let pred = &mut self.pred;
+        return self.map.extract_next(&mut self.next, |key, _| pred(key)).map(|(key, _)| key);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.map.len()))
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>, F: FnMut(&K) -> bool> FusedIterator for ExtractKeysIf<'_, K, S, C, F> {}
@@ -0,0 +1,460 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::ops::RangeBounds;
+
+use crate::summary::{above_lower, below_upper};
+use crate::{Comparator, NodeId, RBColor, RBMap, RBNode, RBTree, Slot, Summary};
+
+/* A tree cut loose from the map's root, together with its black height: the
+ * number of black nodes on any path from its root down to a leaf. The root of
+ * a subtree is always black, so that any two of them can be joined. */
+pub(crate) struct Subtree<K, V, S> {
+    pub(crate) root: RBNode<K, V, S>,
+    pub(crate) height: usize
+}
+
+impl<K, V, S> Subtree<K, V, S> {
+    pub(crate) fn empty() -> Self {
+        Subtree {root: None, height: 0}
+    }
+}
+
+/* The result of splitting a tree around a probe key. */
+struct Split<K, V, S> {
+    below: Subtree<K, V, S>,
+    found: RBNode<K, V, S>, /* the node equal to the probe, unlinked */
+    above: Subtree<K, V, S>
+}
+
+/* What a set operation keeps of two trees `a` and `b`. */
+#[derive(Copy, Clone, PartialEq)]
+enum SetOp {
+    Union,
+    Intersection,
+    Difference, /* the keys of `a` that are not in `b` */
+    SymmetricDifference
+}
+
+/* Join-based split and set algebra, after Blelloch, Ferizovic and Sun, "Just
+ * Join for Parallel Ordered Sets". Everything here works on detached subtrees
+ * of one arena; `self.root` is only written back at the end. Operations on two
+ * maps keep the comparator of whichever map hosts the result, so both must
+ * order their keys the same way. */
+impl<K, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Returns: the number of black nodes on the leftmost path down from `x`. */
+    fn black_height(&self, mut x: RBNode<K, V, S>) -> usize {
+        let mut height = 0;
+        while let Some(id) = x {
+            if self.node(id).color == RBColor::Black {
+                height += 1;
+            }
+            x = self.node(id).left;
+        }
+        return height;
+    }
+
+    /* Cuts `x`, whose black height is `height`, loose from its parent and
+     * blackens its root. */
+    fn detach(&mut self, x: RBNode<K, V, S>, height: usize) -> Subtree<K, V, S> {
+        self.set_parent(x, None);
+        if self.get_color(x) == RBColor::Red {
+            self.set_color(x, RBColor::Black);
+            return Subtree {root: x, height: height + 1};
+        }
+        return Subtree {root: x, height};
+    }
+
+    /* Takes the whole tree out of the map, settled. Its nodes stay in the
+     * arena. */
+    pub(crate) fn take_root(&mut self) -> Subtree<K, V, S> {
+        self.settle();
+        let root = self.root;
+        self.root = None;
+        return Subtree {root, height: self.black_height(root)};
+    }
+
+    pub(crate) fn set_root(&mut self, tree: Subtree<K, V, S>) {
+        self.root = tree.root;
+        self.len = self.get_size(tree.root);
+    }
+
+    /* Makes `k` the parent of `left` and `right`. Returns: `k`. */
+    fn link(&mut self, left: RBNode<K, V, S>, k: NodeId<K, V, S>, right: RBNode<K, V, S>, color: RBColor) -> NodeId<K, V, S> {
+        let x = Some(k);
+        self.set_left(x, left);
+        self.set_right(x, right);
+        self.set_parent(left, x);
+        self.set_parent(right, x);
+        self.set_parent(x, None);
+        self.set_color(x, color);
+        self.update_node(x);
+        return k;
+    }
+
+    /* Rotations that leave fixing the link from the old parent to the caller. */
+    fn rotate_left_detached(&mut self, x: NodeId<K, V, S>) -> NodeId<K, V, S> {
+        let y = self.get_right(Some(x));
+        self.set_right(Some(x), self.get_left(y));
+        self.set_parent(self.get_left(y), Some(x));
+        self.set_left(y, Some(x));
+        self.set_parent(Some(x), y);
+        self.update_node(Some(x));
+        self.update_node(y);
+        return y.expect("INVALID STATE!");
+    }
+
+    fn rotate_right_detached(&mut self, x: NodeId<K, V, S>) -> NodeId<K, V, S> {
+        let y = self.get_left(Some(x));
+        self.set_left(Some(x), self.get_right(y));
+        self.set_parent(self.get_right(y), Some(x));
+        self.set_right(y, Some(x));
+        self.set_parent(Some(x), y);
+        self.update_node(Some(x));
+        self.update_node(y);
+        return y.expect("INVALID STATE!");
+    }
+
+    /* Joins `left`, the single node `k` and `right`, whose keys must be in
+     * that order. The shorter tree is hung off the spine of the taller one at
+     * the matching black height, so this costs O(|left.height - right.height| + 1). */
+    pub(crate) fn join_with(&mut self, left: Subtree<K, V, S>, k: NodeId<K, V, S>, right: Subtree<K, V, S>) -> Subtree<K, V, S> {
+        if left.height > right.height {
+            let height = left.height;
+            let root = self.join_right(left.root, height, k, right);
+            return self.detach(Some(root), height);
+        }
+        if left.height < right.height {
+            let height = right.height;
+            let root = self.join_left(left, k, right.root, height);
+            return self.detach(Some(root), height);
+        }
+        let root = self.link(left.root, k, right.root, RBColor::Black);
+        return Subtree {root: Some(root), height: left.height + 1};
+    }
+
+    /* Walks down the right spine of `l` (black height `height`) to the first
+     * black node as tall as `right` and puts `k` in its place, fixing a red
+     * pair on the way back up with one rotation. */
+    fn join_right(&mut self, l: RBNode<K, V, S>, height: usize, k: NodeId<K, V, S>, right: Subtree<K, V, S>) -> NodeId<K, V, S> {
+        let l_color = self.get_color(l);
+        if l_color == RBColor::Black && height == right.height {
+            return self.link(l, k, right.root, RBColor::Red);
+        }
+        let l_id = l.expect("INVALID STATE!");
+        let child_height = if l_color == RBColor::Black {height - 1} else {height};
+        let t = Some(self.join_right(self.get_right(l), child_height, k, right));
+        self.set_right(l, t);
+        self.set_parent(t, l);
+        if l_color == RBColor::Black && self.get_color(t) == RBColor::Red && self.get_color(self.get_right(t)) == RBColor::Red {
+            self.set_color(self.get_right(t), RBColor::Black);
+            return self.rotate_left_detached(l_id);
+        }
+        self.update_node(l);
+        return l_id;
+    }
+
+    fn join_left(&mut self, left: Subtree<K, V, S>, k: NodeId<K, V, S>, r: RBNode<K, V, S>, height: usize) -> NodeId<K, V, S> {
+        let r_color = self.get_color(r);
+        if r_color == RBColor::Black && height == left.height {
+            return self.link(left.root, k, r, RBColor::Red);
+        }
+        let r_id = r.expect("INVALID STATE!");
+        let child_height = if r_color == RBColor::Black {height - 1} else {height};
+        let t = Some(self.join_left(left, k, self.get_left(r), child_height));
+        self.set_left(r, t);
+        self.set_parent(t, r);
+        if r_color == RBColor::Black && self.get_color(t) == RBColor::Red && self.get_color(self.get_left(t)) == RBColor::Red {
+            self.set_color(self.get_left(t), RBColor::Black);
+            return self.rotate_right_detached(r_id);
+        }
+        self.update_node(r);
+        return r_id;
+    }
+
+    /* Joins two trees whose keys are in order, using the minimum of `right`
+     * as the middle node. */
+    fn join_subtrees(&mut self, left: Subtree<K, V, S>, right: Subtree<K, V, S>) -> Subtree<K, V, S> {
+        if right.root.is_none() {
+            return left;
+        }
+        let (k, rest) = self.split_first(right);
+        return self.join_with(left, k, rest);
+    }
+
+    /* Returns: the minimum node of `tree`, unlinked, and the rest of the tree. */
+    fn split_first(&mut self, tree: Subtree<K, V, S>) -> (NodeId<K, V, S>, Subtree<K, V, S>) {
+        let x = tree.root.expect("INVALID STATE!");
+        let left = self.detach(self.get_left(tree.root), tree.height - 1);
+        let right = self.detach(self.get_right(tree.root), tree.height - 1);
+        if left.root.is_none() {
+            return (x, right);
+        }
+        let (k, rest) = self.split_first(left);
+        return (k, self.join_with(rest, x, right));
+    }
+
+    /* Splits `tree` around a probe key, where `cmp` gives the order of the
+     * probe relative to a node. Every join along the way is between trees of
+     * about the same height, so the whole split costs O(log n). */
+    fn split<F: Fn(&Self, NodeId<K, V, S>) -> Ordering>(&mut self, tree: Subtree<K, V, S>, cmp: &F) -> Split<K, V, S> {
+        let Some(x) = tree.root else {
+            return Split {below: Subtree::empty(), found: None, above: Subtree::empty()};
+        };
+        let left = self.detach(self.get_left(tree.root), tree.height - 1);
+        let right = self.detach(self.get_right(tree.root), tree.height - 1);
+        match cmp(self, x) {
+            Ordering::Equal => {
+                return Split {below: left, found: Some(x), above: right};
+            }
+            Ordering::Less => {
+                let split = self.split(left, cmp);
+                let above = self.join_with(split.above, x, right);
+                return Split {above, ..split};
+            }
+            Ordering::Greater => {
+                let split = self.split(right, cmp);
+                let below = self.join_with(left, x, split.below);
+                return Split {below, ..split};
+            }
+        }
+    }
+
+    /* Frees every node of the subtree rooted at `x`. */
+    fn free_subtree(&mut self, x: RBNode<K, V, S>) {
+        let mut stack = Vec::from_iter(x);
+        while let Some(id) = stack.pop() {
+            let node = self.deallocate(id);
+            stack.extend(node.left);
+            stack.extend(node.right);
+        }
+    }
+
+    /* Splits `a` around each node of `b` in turn and joins the results back
+     * up. When both trees hold a key, the node from `b` is kept if `keep_b`
+     * is set. For trees of sizes m <= n this costs O(m log(n/m + 1)). */
+    fn set_operation(&mut self, a: Subtree<K, V, S>, b: Subtree<K, V, S>, op: SetOp, keep_b: bool) -> Subtree<K, V, S> {
+        let Some(k) = b.root else {
+            if op == SetOp::Intersection {
+                self.free_subtree(a.root);
+                return Subtree::empty();
+            }
+            return a;
+        };
+        if a.root.is_none() {
+            if op == SetOp::Union || op == SetOp::SymmetricDifference {
+                return b;
+            }
+            self.free_subtree(b.root);
+            return Subtree::empty();
+        }
+        let b_left = self.detach(self.get_left(b.root), b.height - 1);
+        let b_right = self.detach(self.get_right(b.root), b.height - 1);
+        let split = self.split(a, &|map: &Self, x| map.compare(&map.node(k).key, &map.node(x).key));
+        let left = self.set_operation(split.below, b_left, op, keep_b);
+        let right = self.set_operation(split.above, b_right, op, keep_b);
+        let middle = match (op, split.found) {
+            (SetOp::Union | SetOp::Intersection, Some(x)) => {
+                let (kept, dropped) = if keep_b {(k, x)} else {(x, k)};
+                self.deallocate(dropped);
+                Some(kept)
+            }
+            (SetOp::Union | SetOp::SymmetricDifference, None) => Some(k),
+            (SetOp::Intersection | SetOp::Difference, None) => {
+                self.deallocate(k);
+                None
+            }
+            (SetOp::Difference | SetOp::SymmetricDifference, Some(x)) => {
+                self.deallocate(k);
+                self.deallocate(x);
+                None
+            }
+        };
+        return match middle {
+            Some(x) => self.join_with(left, x, right),
+            None => self.join_subtrees(left, right)
+        };
+    }
+
+    /* Moves every node of `other` into this map's arena. Returns: the moved
+     * tree, which is not linked to this map's root. Costs O(n) in the size of
+     * `other`'s arena. */
+    fn adopt(&mut self, mut other: Self) -> Subtree<K, V, S> {
+        other.settle();
+        let height = other.black_height(other.root);
+        let mut moved = Vec::with_capacity(other.nodes.len());
+        for slot in other.nodes {
+            moved.push(match slot {
+                Slot::Occupied(node) => Some(self.allocate(node)),
+                Slot::Vacant(_) => None
+            });
+        }
+        let relink = |x: RBNode<K, V, S>| x.map(|x| moved[x.index()].expect("INVALID STATE!"));
+        for &id in moved.iter().flatten() {
+            let node = self.node_mut(id);
+            node.left = relink(node.left);
+            node.right = relink(node.right);
+            node.p = relink(node.p);
+        }
+        return Subtree {root: relink(other.root), height};
+    }
+
+    /* Moves the subtree rooted at `x` out of this arena into `into`'s.
+     * Returns: its root there. */
+    fn move_subtree(&mut self, x: RBNode<K, V, S>, into: &mut Self) -> RBNode<K, V, S> {
+        let id = x?;
+        let mut node = self.deallocate(id);
+        let left = self.move_subtree(node.left, into);
+        let right = self.move_subtree(node.right, into);
+        node.left = left;
+        node.right = right;
+        node.p = None;
+        let moved = Some(into.allocate(node));
+        into.set_parent(left, moved);
+        into.set_parent(right, moved);
+        return moved;
+    }
+
+    /* Combines `self` and `other` inside the arena of the larger of the two,
+     * after moving the smaller one's nodes over. Dropped nodes leave vacant
+     * slots behind, which the result keeps until `shrink_to_fit`. */
+    fn combine(self, other: Self, op: SetOp) -> Self {
+        let self_larger = self.len >= other.len;
+        let (mut map, a, b) = if self_larger {
+            let mut map = self;
+            let a = map.take_root();
+            let b = map.adopt(other);
+            (map, a, b)
+        } else {
+            let mut map = other;
+            let b = map.take_root();
+            let a = map.adopt(self);
+            (map, a, b)
+        };
+        /* The bound needs the smaller tree to be the one split around; only
+         * difference is not symmetric, and it always splits `self`. */
+        let tree = if op != SetOp::Difference && !self_larger {
+            map.set_operation(b, a, op, true)
+        } else {
+            map.set_operation(a, b, op, false)
+        };
+        map.set_root(tree);
+        return map;
+    }
+
+    /* Splits the map in two in O(log n + min(|below|, |above|)), not O(log n):
+     * cutting the tree is logarithmic, but the smaller half then moves to an
+     * arena of its own one entry at a time. The larger half keeps the original
+     * arena, with the moved entries' slots left vacant; `shrink_to_fit` gives
+     * them back. Returns: the entries with keys below `key`, and those with
+     * keys at or above it. */
+    pub fn split_at(mut self, key: &K) -> (Self, Self) where C: Clone {
+        let tree = self.take_root();
+        let Split {below, found, above} = self.split(tree, &|map: &Self, x| map.compare(key, &map.node(x).key));
+        let above = match found {
+            Some(x) => self.join_with(Subtree::empty(), x, above),
+            None => above
+        };
+        let mut other = RBMap {nodes: Vec::new(), free: None, root: None, len: 0, cmp: self.cmp.clone()};
+        if self.get_size(below.root) <= self.get_size(above.root) {
+            let root = self.move_subtree(below.root, &mut other);
+            other.set_root(Subtree {root, height: below.height});
+            self.set_root(above);
+            return (other, self);
+        }
+        let root = self.move_subtree(above.root, &mut other);
+        other.set_root(Subtree {root, height: above.height});
+        self.set_root(below);
+        return (self, other);
+    }
+
+    /* Removes every entry whose key falls in `range`. The tree is split at
+     * both ends of the range and the outer parts are joined back together,
+     * so this costs O(log n) plus O(1) per entry removed. The removed
+     * entries' slots stay in the arena until `shrink_to_fit`. Returns: the
+     * number of entries removed. */
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        let tree = self.take_root();
+        /* The probes never compare equal, so nothing is left in `found`. */
+        let start = self.split(tree, &|map: &Self, x| {
+            if above_lower(&map.cmp, &map.node(x).key, range.start_bound()) {Ordering::Less} else {Ordering::Greater}
+        });
+        let end = self.split(start.above, &|map: &Self, x| {
+            if below_upper(&map.cmp, &map.node(x).key, range.end_bound()) {Ordering::Greater} else {Ordering::Less}
+        });
+        let removed = self.get_size(end.below.root);
+        self.free_subtree(end.below.root);
+        let tree = self.join_subtrees(start.below, end.above);
+        self.set_root(tree);
+        return removed;
+    }
+
+    /* Joins two maps where every key of `self` is less than every key of
+     * `other`. The smaller map's nodes move into the larger one's arena, then
+     * the trees are joined in O(log n). */
+    pub fn join(self, other: Self) -> Self {
+        if let (Some(max), Some(min)) = (self.get_maximum(self.root), other.get_minimum(other.root)) {
+            assert!(self.compare(&self.node(max).key, &other.node(min).key) == Ordering::Less, "join: every key of self must be less than every key of other");
+        }
+        let (mut map, left, right) = if self.len >= other.len {
+            let mut map = self;
+            let left = map.take_root();
+            let right = map.adopt(other);
+            (map, left, right)
+        } else {
+            let mut map = other;
+            let right = map.take_root();
+            let left = map.adopt(self);
+            (map, left, right)
+        };
+        let tree = map.join_subtrees(left, right);
+        map.set_root(tree);
+        return map;
+    }
+}
+
+impl<K, S: Summary<K, ()>, C: Comparator<K>> RBTree<K, S, C> {
+    /* Splits in O(log n + min(|below|, |above|)), moving the smaller half to
+     * an arena of its own; see `RBMap::split_at`. Returns: the keys below
+     * `key`, and the keys at or above it. */
+    pub fn split_at(self, key: &K) -> (Self, Self) where C: Clone {
+        let (below, above) = self.map.split_at(key);
+        return (RBTree {map: below}, RBTree {map: above});
+    }
+
+    /* Removes every key in `range`, in O(log n) plus O(1) per key removed.
+     * Their slots stay in the arena until `shrink_to_fit`. Returns: the
+     * number of keys removed. */
+    pub fn remove_range<R: RangeBounds<K>>(&mut self, range: R) -> usize {
+        return self.map.remove_range(range);
+    }
+
+    /* Returns: the keys of both sets, where every key of `self` must be less
+     * than every key of `other`. */
+    pub fn join(self, other: Self) -> Self {
+        return RBTree {map: self.map.join(other.map)};
+    }
+
+    /* Returns: the keys in either set. For sets of sizes m <= n this costs
+     * O(m log(n/m + 1)), plus O(1) for each duplicate key dropped. Dropped
+     * keys leave their slots in the arena until `shrink_to_fit`. */
+    pub fn union(self, other: Self) -> Self {
+        return RBTree {map: self.map.combine(other.map, SetOp::Union)};
+    }
+
+    /* Returns: the keys in both sets, kept from `self`. Costs as `union`, plus
+     * O(1) for each key dropped. */
+    pub fn intersection(self, other: Self) -> Self {
+        return RBTree {map: self.map.combine(other.map, SetOp::Intersection)};
+    }
+
+    /* Returns: the keys of `self` that are not in `other`. Costs as `union`,
+     * plus O(1) for each key dropped. */
+    pub fn difference(self, other: Self) -> Self {
+        return RBTree {map: self.map.combine(other.map, SetOp::Difference)};
+    }
+
+    /* Returns: the keys in exactly one of the sets. Costs as `union`. */
+    pub fn symmetric_difference(self, other: Self) -> Self {
+        return RBTree {map: self.map.combine(other.map, SetOp::SymmetricDifference)};
+    }
+}
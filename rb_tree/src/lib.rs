@@ -1,22 +1,114 @@
-use std::fmt::Debug;
-use std::rc::Rc;
-use std::rc::Weak;
-use std::cell::RefCell;
+#![allow(clippy::needless_return)]
+/* Without the default `std` feature only `alloc` is needed. Tests always run
+ * with `std`. */
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
-pub struct RBTree<K: std::cmp::PartialOrd> {
-    root: RBNode<K>
+extern crate alloc;
+
+use alloc::collections::TryReserveError;
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::{self, Debug};
+use core::marker::PhantomData;
+use core::ops::{Bound, RangeBounds};
+
+mod avl;
+mod bulk;
+mod codec;
+mod compare;
+#[cfg(feature = "std")]
+mod concurrent;
+mod cursor;
+mod entry;
+mod interval;
+mod iter;
+mod join;
+mod multiset;
+mod ordered_set;
+mod persistent;
+mod render;
+mod summary;
+mod treap;
+mod validate;
+
+pub use avl::AvlTree;
+pub use codec::{Codec, DecodeError};
+pub use compare::{Comparator, NaturalOrder};
+#[cfg(feature = "std")]
+pub use concurrent::ConcurrentRBTree;
+pub use cursor::{Cursor, CursorMut};
+pub use entry::{Entry, OccupiedEntry, VacantEntry};
+pub use interval::{Interval, IntervalTree, Overlapping};
+pub use iter::{Iter, IntoIter, Keys, Values, IntoKeys, IntoValues, Range, RangeKeys, ExtractIf, ExtractKeysIf};
+pub use multiset::RBMultiSet;
+pub use ordered_set::{NodeIter, OrderedSet};
+pub use persistent::{PersistentIter, PersistentRBTree};
+pub use render::Render;
+pub use summary::Summary;
+pub use treap::Treap;
+pub use validate::{Invariant, Side, Violation};
+
+/* An ordered set of keys. This is a thin wrapper over an `RBMap` whose values
+ * are `()`, so every balancing operation is shared with the map. `S` is an
+ * optional per-subtree summary over the keys (see `Summary`), and `C` is the
+ * order the keys are kept in (see `Comparator`). */
+pub struct RBTree<K, S = (), C = NaturalOrder> {
+    map: RBMap<K, (), S, C>
+}
+
+/* An ordered map from keys to values, balanced as a red-black tree. Every node
+ * also carries a summary `S` of its subtree, which defaults to the empty `()`.
+ * The nodes live in one arena and link to each other by index. Slots freed by
+ * removals are threaded onto a free list and reused by later inserts. */
+pub struct RBMap<K, V, S = (), C = NaturalOrder> {
+    nodes: Vec<Slot<K, V, S>>,
+    free: RBNode<K, V, S>, /* first vacant slot, if any */
+    root: RBNode<K, V, S>,
+    len: usize,
+    cmp: C
+}
+
+/* The index of a node in the arena of the map that created it. Indices are
+ * 32 bits wide to keep nodes small, which caps a map at `u32::MAX` nodes. */
+pub struct NodeId<K, V = (), S = ()> {
+    index: u32,
+    marker: NodeMarker<K, V, S>
 }
 
-pub type RBNode<K> = Option<Rc<RefCell<RBNodeInternal<K>>>>;
-type WeakRBNode<K> = Option<Weak<RefCell<RBNodeInternal<K>>>>;
+/* Ties an id to its map's types without owning any of them, so ids stay
+ * `Copy`, `Send` and `Sync` whatever those types are. */
+type NodeMarker<K, V, S> = PhantomData<fn() -> (K, V, S)>;
+
+pub type RBNode<K, V = (), S = ()> = Option<NodeId<K, V, S>>;
+
+/* The entries on either side of a gap between adjacent keys, where None is
+ * the ghost position past both ends. */
+type Gap<K, V, S> = (RBNode<K, V, S>, RBNode<K, V, S>);
+
+/* The node holding a key, or else the node a new node for it would hang off
+ * and, when known, the gap it would fill. */
+type Position<K, V, S> = Result<NodeId<K, V, S>, (RBNode<K, V, S>, Option<Gap<K, V, S>>)>;
+
+/* The node now holding an inserted key, the value it replaced, and its
+ * neighbours when known. */
+type Inserted<K, V, S> = (NodeId<K, V, S>, Option<V>, Option<Gap<K, V, S>>);
+
+enum Slot<K, V, S> {
+    Occupied(RBNodeInternal<K, V, S>),
+    Vacant(RBNode<K, V, S>) /* the next vacant slot */
+}
 
 #[derive(Debug)]
-pub struct RBNodeInternal<K: std::cmp::PartialOrd> {
+pub struct RBNodeInternal<K, V = (), S = ()> {
     color: RBColor,
     key: K,
-    right: RBNode<K>,
-    left: RBNode<K>,
-    p: WeakRBNode<K>
+    value: V,
+    size: usize, /* number of nodes in the subtree rooted here */
+    summary: S, /* summary of every entry in the subtree rooted here */
+    dirty: bool, /* size and summary are stale until the next `settle` */
+    right: RBNode<K, V, S>,
+    left: RBNode<K, V, S>,
+    p: RBNode<K, V, S>
 }
 
 #[derive(Copy, Clone, PartialEq, Debug)]
@@ -25,8 +117,66 @@ pub enum RBColor {
     Black
 }
 
+/* Why a checked insertion could not add a node. The tree is left unchanged. */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum InsertError {
+    CapacityOverflow, /* the arena already holds u32::MAX nodes */
+    Alloc(TryReserveError) /* memory for another node could not be allocated */
+}
+
+impl fmt::Display for InsertError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            InsertError::CapacityOverflow => write!(f, "tree cannot hold more than u32::MAX nodes"),
+            InsertError::Alloc(error) => write!(f, "could not allocate a node: {}", error)
+        }
+    }
+}
+
+impl core::error::Error for InsertError {
+    fn source(&self) -> Option<&(dyn core::error::Error + 'static)> {
+        match self {
+            InsertError::Alloc(error) => Some(error),
+            InsertError::CapacityOverflow => None
+        }
+    }
+}
+
+impl<K, V, S> NodeId<K, V, S> {
+    fn new(index: usize) -> Self {
+        let index = u32::try_from(index).expect("RBMap cannot hold more than u32::MAX nodes");
+        NodeId {index, marker: PhantomData}
+    }
+
+    fn index(self) -> usize {
+        return self.index as usize;
+    }
+}
+
+impl<K, V, S> Clone for NodeId<K, V, S> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K, V, S> Copy for NodeId<K, V, S> {}
 
-/**
+impl<K, V, S> PartialEq for NodeId<K, V, S> {
+    fn eq(&self, other: &Self) -> bool {
+        self.index == other.index
+    }
+}
+
+impl<K, V, S> Eq for NodeId<K, V, S> {}
+
+impl<K, V, S> Debug for NodeId<K, V, S> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "NodeId({})", self.index)
+    }
+}
+
+
+/*
  RB-Insert(T,z)
     y = nil[T]
     x = root[T]
@@ -50,607 +200,1398 @@ pub enum RBColor {
     RB-Insert-fixup(T,z)
 */
 
-fn clone_node<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> RBNode<T> {
-    node.as_ref().map(|rc| Rc::clone(&rc))
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, PartialEq)]
+enum NodeChildType {
+    LEFT,
+    RIGHT
 }
 
-fn clone_weak_node<T: std::cmp::PartialOrd>(node: &WeakRBNode<T>) -> WeakRBNode<T> {
-    node.as_ref().map(|rc| Weak::clone(&rc))
+#[cfg(test)]
+std::thread_local! {
+    /* Nodes looked at by this thread, for tests that bound the work done. */
+    static VISITS: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
 }
 
-fn get_left<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> RBNode<T> {
-    match node.as_ref() {
-        None => None,
-        Some(val) => {
-            clone_node(&val.borrow().left)
+/* Arena access. Following a link is a bounds-checked index into `nodes`. A
+ * link to a vacant slot means the tree is corrupt. */
+impl<K, V, S, C> RBMap<K, V, S, C> {
+    fn node(&self, x: NodeId<K, V, S>) -> &RBNodeInternal<K, V, S> {
+        #[cfg(test)]
+        VISITS.with(|visits| visits.set(visits.get() + 1));
+        match &self.nodes[x.index()] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => panic!("INVALID STATE!")
         }
     }
-}
 
-fn get_right<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> RBNode<T> {
-    match node.as_ref() {
-        None => None,
-        Some(val) => {
-            clone_node(&val.borrow().right)
+    fn node_mut(&mut self, x: NodeId<K, V, S>) -> &mut RBNodeInternal<K, V, S> {
+        #[cfg(test)]
+        VISITS.with(|visits| visits.set(visits.get() + 1));
+        match &mut self.nodes[x.index()] {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => panic!("INVALID STATE!")
         }
     }
-}
 
-fn get_parent<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> WeakRBNode<T> {
-    match clone_node(node) {
-        None => None,
-        Some(val) => {
-            clone_weak_node(&val.borrow_mut().p)
+    /* Moves `node` into the first vacant slot, or onto the end of the arena if
+     * there is none. Returns: the node's id. */
+    fn allocate(&mut self, node: RBNodeInternal<K, V, S>) -> NodeId<K, V, S> {
+        match self.free {
+            Some(x) => {
+                self.free = match core::mem::replace(&mut self.nodes[x.index()], Slot::Occupied(node)) {
+                    Slot::Vacant(next) => next,
+                    Slot::Occupied(_) => panic!("INVALID STATE!")
+                };
+                return x;
+            }
+            None => {
+                self.nodes.push(Slot::Occupied(node));
+                return NodeId::new(self.nodes.len() - 1);
+            }
         }
     }
-}
 
-fn get_color<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> RBColor {
-    match node.as_ref() {
-        None => RBColor::Black,
-        Some(val) => {
-            val.borrow().color
+    /* Makes room for one more node, so that the next `allocate` cannot fail. */
+    fn reserve_node(&mut self) -> Result<(), InsertError> {
+        if self.free.is_some() {
+            return Ok(());
         }
+        if self.nodes.len() > u32::MAX as usize {
+            return Err(InsertError::CapacityOverflow);
+        }
+        return self.nodes.try_reserve(1).map_err(InsertError::Alloc);
     }
-}
 
-fn set_left<T: std::cmp::PartialOrd>(node: &RBNode<T>, left: RBNode<T>) {
-    node.as_ref().map(|val| {
-        val.borrow_mut().left = left;
-    });
-}
-
-fn set_right<T: std::cmp::PartialOrd>(node: &RBNode<T>, right: RBNode<T>) {
-    node.as_ref().map(|val| {
-        val.borrow_mut().right = right;
-    });
-}
-
-fn set_parent<T: std::cmp::PartialOrd>(node: &RBNode<T>, parent: WeakRBNode<T>) {
-    node.as_ref().map(|val| {
-        val.borrow_mut().p = parent;
-    });
-}
+    /* Moves the node out of its slot and pushes the slot onto the free list.
+     * The node must already be unlinked from the tree. */
+    fn deallocate(&mut self, x: NodeId<K, V, S>) -> RBNodeInternal<K, V, S> {
+        let slot = core::mem::replace(&mut self.nodes[x.index()], Slot::Vacant(self.free));
+        self.free = Some(x);
+        match slot {
+            Slot::Occupied(node) => node,
+            Slot::Vacant(_) => panic!("INVALID STATE!")
+        }
+    }
 
-fn set_color<T: std::cmp::PartialOrd>(node: &RBNode<T>, color: RBColor) {
-    node.as_ref().map(|val| {
-        val.borrow_mut().color = color;
-    });
-}
+    fn key_value(&self, x: NodeId<K, V, S>) -> (&K, &V) {
+        let node = self.node(x);
+        return (&node.key, &node.value);
+    }
 
-fn to_weak<T: std::cmp::PartialOrd>(node: &RBNode<T>) -> WeakRBNode<T> {
-    match node.as_ref() {
-        None => None,
-        Some(val) => {
-            Some(Rc::downgrade(val))
+    fn get_left(&self, x: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        match x {
+            None => None,
+            Some(x) => self.node(x).left
         }
     }
-}
 
-fn to_strong<T: std::cmp::PartialOrd>(node: &WeakRBNode<T>) -> RBNode<T> {
-    match node.as_ref() {
-        None => None,
-        Some(val) => {
-            Some(Weak::upgrade(val).expect("INVALID STATE!"))
+    fn get_right(&self, x: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        match x {
+            None => None,
+            Some(x) => self.node(x).right
         }
     }
-}    
 
-impl<K: std::cmp::PartialOrd> PartialEq<RBNodeInternal<K>> for RBNodeInternal<K> {
-    fn eq(&self, other: &Self) -> bool {
-        self.key == other.key && self.left == other.left && self.right == other.right
+    fn get_parent(&self, x: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        match x {
+            None => None,
+            Some(x) => self.node(x).p
+        }
     }
-} 
-
-#[derive(Copy, Clone, PartialEq)]
-enum NodeChildType {
-    LEFT,
-    RIGHT
-}
 
-impl<K: std::cmp::PartialOrd + Debug> RBTree<K> {
-    pub fn new() -> RBTree<K> {
-        RBTree {root: None}
+    fn get_color(&self, x: RBNode<K, V, S>) -> RBColor {
+        match x {
+            None => RBColor::Black,
+            Some(x) => self.node(x).color
+        }
     }
 
-    pub fn insert(&mut self, key: K) -> &mut Self {
-        // println!("Inserting {:?}!", key);
-        // self.print();
-        if self.contains(&key) {return self;} /*  */
-        let mut y: RBNode<K> = None;
-        let mut x: RBNode<K> = clone_node(&self.root);
-        while let Some(rc_node) = clone_node(&x) {
-            y = x;
-            if key < rc_node.borrow().key {
-                x = clone_node(&rc_node.borrow().left);
-            } else {
-                x = clone_node(&rc_node.borrow().right);
-            }
-        }
-        let z: RBNodeInternal<K> = RBNodeInternal {
-            color: RBColor::Red,
-            key: key,
-            right: None,
-            left: None,
-            p: y.as_ref().map(|rc| Rc::downgrade(&rc))
+    /* Returns: the number of nodes under `x`. A stale subtree (see
+     * `mark_to_root`) is counted from its children instead. */
+    fn get_size(&self, x: RBNode<K, V, S>) -> usize {
+        let Some(x) = x else {
+            return 0;
         };
-        let z_node;
-        match y {
-            None => {
-                z_node = Some(Rc::new(RefCell::new(z)));
-                self.root = clone_node(&z_node);
-            }
-            Some(rc) => {
-                if z.key < rc.borrow().key {
-                    z_node = Some(Rc::new(RefCell::new(z)));
-                    rc.borrow_mut().left = clone_node(&z_node);
-                } else {
-                    z_node = Some(Rc::new(RefCell::new(z)));
-                    rc.borrow_mut().right = clone_node(&z_node);
-                }
-            }
+        let node = self.node(x);
+        if node.dirty {
+            return 1 + self.get_size(node.left) + self.get_size(node.right);
         }
-        self.insert_fixup(z_node);
-        return self;
-    }
-
-/*
-RB-Insert-fixup(T,z)
-  while color[p[z]] = RED {
-    if p[z] == left[p[p[z]]] {
-         y = right[p[p[z]]]
-         if color[y] = RED {
-             color[p[z]] = BLACK
-             color[y] = BLACK
-             color[p[p[z]]] = RED
-             z = p[p[z]]
-         }
-         else {
-             if z = right[p[z]] {
-                  z = p[z]
-                  LEFT-Rotate(T,z)
-             }
-             color[p[z]] = BLACK
-             color[p[p[z]]] = RED
-             RIGHT-Rotate(T,p[p[z]])
-         }
-    }
-    else {
-         y = left[p[p[z]]]
-         if color[y] = RED {
-             color[p[z]] = BLACK
-             color[y] = BLACK
-             color[p[p[z]]] = RED
-             z = p[p[z]]
-         }
-         else
-             {
-             if z = left[p[z]] {
-                  z = p[z]
-                  RIGHT-Rotate(T,z)
-             }
-             color[p[z]] = BLACK
-             color[p[p[z]]] = RED
-             LEFT-Rotate(T,p[p[z]])
-         }
+        return node.size;
     }
-    color[root[T]] = BLACK
-  }
-*/
 
-    fn insert_fixup(&mut self, mut z: RBNode<K>) {
-        while get_color(&to_strong(&get_parent(&z))) == RBColor::Red {
-            // println!("\nAttempted fixup for where k={:?}.", get_key(&z).unwrap());
-            // self.print();
-            if to_strong(&get_parent(&z)) == get_left(&to_strong(&get_parent(&to_strong(&get_parent(&z))))) {
-                /* If the parent is to the left of the grandparent */
-                /* y is z's uncle */
-                let y = get_right(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
-                if get_color(&y) == RBColor::Red {
-                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
-                    set_color(&y, RBColor::Black);
-                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
-                    z = to_strong(&get_parent(&to_strong(&get_parent(&z))));
-                } else {
-                    if z == get_right(&to_strong(&get_parent(&z))) {
-                        z = to_strong(&get_parent(&z));
-                        self.left_rotate(&mut z);
-                    }
-                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
-                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
-                    self.right_rotate(&mut to_strong(&get_parent(&to_strong(&get_parent(&z)))));
-                }
-            } else {
-                /* If the parent is to the right of the grandparent */
-                let y = get_left(&to_strong(&get_parent(&to_strong(&get_parent(&z)))));
-                if get_color(&y) == RBColor::Red {
-                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
-                    set_color(&y, RBColor::Black);
-                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
-                    z = to_strong(&get_parent(&to_strong(&get_parent(&z))));
-                } else {
-                    if z == get_left(&to_strong(&get_parent(&z))) {
-                        z = to_strong(&get_parent(&z));
-                        self.right_rotate(&mut z);
-                    }
-                    set_color(&to_strong(&get_parent(&z)), RBColor::Black);
-                    set_color(&to_strong(&get_parent(&to_strong(&get_parent(&z)))), RBColor::Red);
-                    self.left_rotate(&mut to_strong(&get_parent(&to_strong(&get_parent(&z)))));
-                }
-            }
+    fn set_left(&mut self, x: RBNode<K, V, S>, left: RBNode<K, V, S>) {
+        if let Some(x) = x {
+            self.node_mut(x).left = left;
         }
-        set_color(&self.root, RBColor::Black);
     }
 
-    pub fn contains(&self, key: &K) -> bool {
-        return self.find(key).is_some();
+    fn set_right(&mut self, x: RBNode<K, V, S>, right: RBNode<K, V, S>) {
+        if let Some(x) = x {
+            self.node_mut(x).right = right;
+        }
     }
 
-    fn find(&self, key: &K) -> RBNode<K> {
-        let mut x = clone_node(&self.root);
-        while x.is_some() {
-            let rc = clone_node(&x).unwrap();
-            let node_key = &rc.borrow().key;
-            if key < node_key {
-                x = clone_node(&rc.borrow().left);
-            } else if key == node_key {
-                return x;
-            } else {
-                x = clone_node(&rc.borrow().right);
-            }
+    fn set_parent(&mut self, x: RBNode<K, V, S>, parent: RBNode<K, V, S>) {
+        if let Some(x) = x {
+            self.node_mut(x).p = parent;
         }
-        return None; // x must be None
     }
 
-    pub fn remove(&mut self, key: &K) -> &mut Self {
-        self.remove_node(&self.find(key))
+    fn set_color(&mut self, x: RBNode<K, V, S>, color: RBColor) {
+        if let Some(x) = x {
+            self.node_mut(x).color = color;
+        }
     }
+}
 
-    
-    pub fn remove_node(&mut self, z: &RBNode<K>) -> &mut Self {
-        if z.is_none() {return self;}
-        let mut y = clone_node(z);
-        let mut y_original_color = get_color(&y);
-        let mut x_parent: RBNode<K>;
-        let mut x_parent_relationship: NodeChildType;
-        let mut x: RBNode<K>;
-        if get_left(z).is_none() {
-            x = get_right(z);
-            x_parent = clone_node(&to_strong(&get_parent(&z)));
-            x_parent_relationship = if z == &get_left(&to_strong(&get_parent(&z))) {
-                NodeChildType::LEFT
-            } else {
-                NodeChildType::RIGHT
-            };
-            self.transplant(z, &get_right(z));
-        } else if get_right(z).is_none() {
-            x = get_left(z);
-            x_parent = clone_node(&to_strong(&get_parent(&z)));
-            x_parent_relationship = if z == &get_left(&to_strong(&get_parent(&z))) {
-                NodeChildType::LEFT
-            } else {
-                NodeChildType::RIGHT
+impl<K, V, S: Summary<K, V>, C> RBMap<K, V, S, C> {
+    /* Recomputes the size and summary of `x` from the values stored in its
+     * children, which must be correct unless `x` is itself stale. */
+    fn update_node(&mut self, x: RBNode<K, V, S>) {
+        if let Some(x) = x {
+            let node = self.node(x);
+            let size = 1 + node.left.map_or(0, |l| self.node(l).size) + node.right.map_or(0, |r| self.node(r).size);
+            let entry = S::from_entry(&node.key, &node.value);
+            let summary = match (node.left, node.right) {
+                (None, None) => entry,
+                (Some(l), None) => S::combine(&self.node(l).summary, &entry),
+                (None, Some(r)) => S::combine(&entry, &self.node(r).summary),
+                (Some(l), Some(r)) => S::combine(&S::combine(&self.node(l).summary, &entry), &self.node(r).summary)
             };
-            self.transplant(z, &get_left(z));
-        } else {
-            y = Self::get_minimum(&get_right(z));
-            y_original_color = get_color(&y);
-            x = get_right(&y);
-            x_parent = clone_node(&to_strong(&get_parent(&y)));
-            if y != get_right(&z) { /* the minimum is farther down the tree. */
-                self.transplant(&y, &get_right(&y));
-                set_right(&y, get_right(&z));
-                set_parent(&get_right(&y), to_weak(&y));
-                x_parent_relationship = NodeChildType::LEFT;
-            } else {
-                set_parent(&x, to_weak(&y));
-                x_parent = clone_node(&y);
-                x_parent_relationship = NodeChildType::RIGHT;
-            }
-            self.transplant(&z, &y);
-            set_left(&y, get_left(&z));
-            set_parent(&get_left(&y), to_weak(&y));
-            set_color(&y, get_color(&z));
-        }
-        if y_original_color == RBColor::Black {
-            self.remove_fixup(x, x_parent, x_parent_relationship);
+            let node = self.node_mut(x);
+            node.size = size;
+            node.summary = summary;
         }
-        return self;
     }
 
-    fn get_minimum(z: &RBNode<K>) -> RBNode<K> {
-        let mut z_node = clone_node(&z);
-        while get_left(&z_node).is_some() {
-            z_node = get_left(&z_node);
+    /* Recomputes sizes and summaries from `x` up to the root. */
+    fn update_nodes_to_root(&mut self, mut x: RBNode<K, V, S>) {
+        while x.is_some() {
+            self.update_node(x);
+            x = self.get_parent(x);
         }
-        return z_node;
     }
 
-    fn remove_fixup(&mut self, mut x: RBNode<K>, mut x_parent: RBNode<K>, mut x_parent_relationship: NodeChildType) {
-        while x != self.root && get_color(&x) == RBColor::Black {
-            if x_parent_relationship == NodeChildType::LEFT {
-            // if x == get_left(&to_strong(&get_parent(&x))) { 
-                // let mut w = get_right(&to_strong(&get_parent(&x)));
-                let mut w = get_right(&x_parent);
-                if get_color(&w) == RBColor::Red {
-                    set_color(&w, RBColor::Black);
-                    set_color(&x_parent, RBColor::Red);
-                    self.left_rotate(&mut x_parent); /* x_parent is still the parent of x. */
-                    w = get_right(&x_parent);
-                }
-                if get_color(&get_left(&w)) == RBColor::Black && get_color(&get_right(&w)) == RBColor::Black {
-                    set_color(&w, RBColor::Red);
-                    x = x_parent;
-                    x_parent = to_strong(&get_parent(&x));
-                    x_parent_relationship = if x == get_left(&x_parent) {
-                        NodeChildType::LEFT
-                    } else {
-                        NodeChildType::RIGHT
-                    };
-                } else {
-                    if get_color(&get_right(&w)) == RBColor::Black {
-                        set_color(&get_left(&w), RBColor::Black);
-                        set_color(&w, RBColor::Red);
-                        self.right_rotate(&mut w);
-                        w = get_right(&x_parent);
-                    }
-                    set_color(&w, get_color(&x_parent));
-                    set_color(&x_parent, RBColor::Black);
-                    set_color(&get_right(&w), RBColor::Black);
-                    self.left_rotate(&mut x_parent);
-                    break;
-                }
-            } else {
-                let mut w = get_left(&x_parent);
-                if get_color(&w) == RBColor::Red {
-                    set_color(&w, RBColor::Black);
-                    set_color(&x_parent, RBColor::Red);
-                    self.right_rotate(&mut x_parent);
-                    w = get_left(&x_parent);
-                }
-                if get_color(&get_left(&w)) == RBColor::Black && get_color(&get_right(&w)) == RBColor::Black {
-                    set_color(&w, RBColor::Red);
-                    x = x_parent;
-                    x_parent = to_strong(&get_parent(&x));
-                    x_parent_relationship = if x == get_left(&x_parent) {
-                        NodeChildType::LEFT
-                    } else {
-                        NodeChildType::RIGHT
-                    };
-                } else {
-                    if get_color(&get_left(&w)) == RBColor::Black {
-                        set_color(&get_right(&w), RBColor::Black);
-                        set_color(&w, RBColor::Red);
-                        self.left_rotate(&mut w);
-                        w = get_left(&x_parent);
-                    }
-                    set_color(&w, get_color(&x_parent));
-                    set_color(&x_parent, RBColor::Black);
-                    set_color(&get_left(&w), RBColor::Black);
-                    self.right_rotate(&mut x_parent);
-                    break;
-                }
+    /* Marks `x` and its ancestors stale instead of recomputing them. Marking
+     * stops at the first ancestor already marked, so between two `settle`s
+     * each node is marked at most once. Readers work a stale subtree out from
+     * its children, and every change to the shape of the tree settles it
+     * first, so marks left behind by a leaked cursor only cost time. */
+    fn mark_to_root(&mut self, mut x: RBNode<K, V, S>) {
+        while let Some(id) = x {
+            let node = self.node_mut(id);
+            if node.dirty {
+                return;
             }
+            node.dirty = true;
+            x = node.p;
         }
-        set_color(&x, RBColor::Black);
     }
 
-    fn transplant(&mut self, u: &RBNode<K>, v: &RBNode<K>) {
-        if get_parent(u).is_none() {
-            self.root = clone_node(v);
-        } else if u == &get_left(&to_strong(&get_parent(u))) {
-            set_left(&to_strong(&get_parent(u)), clone_node(v));
-        } else {
-            set_right(&to_strong(&get_parent(u)), clone_node(v));
-        }
-        set_parent(&v, get_parent(u));
+    /* Recomputes every node marked by `mark_to_root`, children first. Every
+     * ancestor of a marked node is marked too, so this only visits the marked
+     * nodes and their children. */
+    fn settle(&mut self) {
+        self.settle_under(self.root);
     }
 
-/*
-Left-Rotate(T,x)
-    y = right[x]
-    right[x] = left[y]
-    p[left[y]] = x
-    p[y] = p[x]
-
-
-    if p[x] == nil[T] then root[T] = y
-    else
-       if x == left[p[x]] then left[p[x]] = y
-       else
-          right[p[x]] = y
-    left[y] = x
-    p[x] = y
-*/
-    fn left_rotate(&mut self, x: &mut RBNode<K>) {
-        if x.is_none() {
+    fn settle_under(&mut self, x: RBNode<K, V, S>) {
+        let Some(id) = x else {
+            return;
+        };
+        let node = self.node_mut(id);
+        if !node.dirty {
             return;
         }
-        let y = get_right(x);
-        assert!(y.is_some());
-        set_right(x, clone_node(&get_left(&y)));
-        set_parent(&get_left(&y), to_weak(&x));
-        set_parent(&get_left(&y), to_weak(&x));
-        set_parent(&y, get_parent(&x));
+        node.dirty = false;
+        let (left, right) = (node.left, node.right);
+        self.settle_under(left);
+        self.settle_under(right);
+        self.update_node(x);
+    }
 
-        if get_parent(x).is_none() {
-            self.root = clone_node(&y);
-        } else {
-            if x == &mut get_left(&to_strong(&get_parent(x))) {
-                set_left(&to_strong(&get_parent(&x)), clone_node(&y));
-            } else {
-                set_right(&to_strong(&get_parent(&x)), clone_node(&y));
-            }
+    /* Returns: the summary of the subtree at `x`, worked out from its children
+     * when it is stale. */
+    fn summary_of(&self, x: RBNode<K, V, S>) -> S {
+        let Some(x) = x else {
+            return S::empty();
+        };
+        let node = self.node(x);
+        if node.dirty {
+            let entry = S::from_entry(&node.key, &node.value);
+            return S::combine(&S::combine(&self.summary_of(node.left), &entry), &self.summary_of(node.right));
         }
-        set_left(&y, clone_node(&x));
-        set_parent(&x, to_weak(&y));
+        return node.summary.clone();
     }
+}
 
-    fn right_rotate(&mut self, x: &mut RBNode<K>) {
-        if x.is_none() {
-            return;
+/* Navigation helpers. These only follow links, so unlike the balancing code
+ * they need neither `K: Debug` nor a comparator. */
+impl<K, V, S, C> RBMap<K, V, S, C> {
+    fn get_minimum(&self, z: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        let mut z_node = z;
+        while self.get_left(z_node).is_some() {
+            z_node = self.get_left(z_node);
         }
-        let y = get_left(x);
-        assert!(y.is_some());
-        set_left(x, clone_node(&get_right(&y)));
-        set_parent(&get_right(&y), to_weak(&x));
-        set_parent(&get_right(&y), to_weak(&x));
-        set_parent(&y, get_parent(&x));
+        return z_node;
+    }
 
-        if get_parent(x).is_none() {
-            self.root = clone_node(&y);
-        } else {
-            if x == &mut get_left(&to_strong(&get_parent(x))) {
-                set_left(&to_strong(&get_parent(&x)), clone_node(&y));
-            } else {
-                set_right(&to_strong(&get_parent(&x)), clone_node(&y));
-            }
+    fn get_maximum(&self, z: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        let mut z_node = z;
+        while self.get_right(z_node).is_some() {
+            z_node = self.get_right(z_node);
         }
-        set_right(&y, clone_node(&x));
-        set_parent(&x, to_weak(&y));
+        return z_node;
     }
 
-    fn is_rb_tree(&self) -> bool {
-        if get_color(&self.root) == RBColor::Red {
-            return false;
-        }
-        if !self.black_height_invariant_satisfied() {
-            return false;
+    /* Returns: the in-order successor of `z`, found through the parent links. */
+    fn get_successor(&self, z: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        if self.get_right(z).is_some() {
+            return self.get_minimum(self.get_right(z));
         }
-        if !self.adjacent_red_invariant_satisfied(&self.root) {
-            return false;
+        let mut x = z;
+        let mut y = self.get_parent(x);
+        while y.is_some() && x == self.get_right(y) {
+            x = y;
+            y = self.get_parent(x);
         }
-        return true;
+        return y;
     }
-    
-    fn black_height_invariant_satisfied(&self) -> bool {
-        return self.get_black_height(&self.root) != -1;
-    }
-    
-    /* Returns: the black height of the node (inclusive) or -1 if the black height
-     * invariant is broken */
-    fn get_black_height(&self, node: &RBNode<K>) -> i32 {
-        if node.is_none() {
-            return 0;
+
+    /* Returns: the in-order predecessor of `z`, found through the parent links. */
+    fn get_predecessor(&self, z: RBNode<K, V, S>) -> RBNode<K, V, S> {
+        if self.get_left(z).is_some() {
+            return self.get_maximum(self.get_left(z));
         }
-        let lft_height = self.get_black_height(&get_left(node));
-        let rgt_height = self.get_black_height(&get_right(node));
-        if lft_height == rgt_height {
-            return lft_height + match get_color(node) {RBColor::Red => 0, RBColor::Black => 1};
+        let mut x = z;
+        let mut y = self.get_parent(x);
+        while y.is_some() && x == self.get_left(y) {
+            x = y;
+            y = self.get_parent(x);
         }
-        println!("bad node:{:?}", node);
-        return -1;
-    }
-    
-    fn adjacent_red_invariant_satisfied(&self, node: &RBNode<K>) -> bool {
-        return node.is_none() || match get_color(node) {
-            RBColor::Black => true,
-            RBColor::Red => get_color(&get_left(node)) == RBColor::Black && get_color(&get_right(node)) == RBColor::Black
-        } && self.adjacent_red_invariant_satisfied(&get_left(node)) && self.adjacent_red_invariant_satisfied(&get_right(node));
+        return y;
     }
-}
 
-#[derive(Copy, Clone, PartialEq)]
-enum NodeType {ROOT, LEFT, RIGHT}
-impl<K: std::cmp::PartialOrd + Debug> RBTree<K> {
-    fn print(&self) {
-        let s = String::from("");
-        Self::print_internal(&self.root, 0, NodeType::ROOT, s);
-    }
-    
-    fn print_internal(node: &RBNode<K>, indent: u32, nt: NodeType, s: String) {
-        if let Some(rf) = node {
-            let left_node = &rf.borrow().left;
-            let right_node = &rf.borrow().right;
-            let mut lft_str = s.clone();
-            let mut rgt_str = s.clone();
-            if nt == NodeType::ROOT {
-                lft_str.push_str("  ");
-                rgt_str.push_str("  ");
-            } else if nt == NodeType::LEFT {
-                lft_str.push_str("  ");
-                rgt_str.push_str("| ");
-            } else {
-                lft_str.push_str("| ");
-                rgt_str.push_str("  ");
-            }
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
 
-            Self::print_internal(right_node, indent + 1, NodeType::RIGHT, rgt_str);
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
 
-            print!("{}", s);
-            if 0 < indent {
-                if nt == NodeType::LEFT {
-                    print!("└");
-                } else {
-                    print!("┌");
+    /* Slots freed by removals, splits and set operations stay in the arena for
+     * later inserts to reuse. This packs the nodes into the front of the arena
+     * and hands the rest of its memory back, in O(n) over the arena. */
+    pub fn shrink_to_fit(&mut self) {
+        let mut moved = Vec::with_capacity(self.nodes.len());
+        let mut next = 0;
+        for slot in &self.nodes {
+            moved.push(match slot {
+                Slot::Occupied(_) => {
+                    next += 1;
+                    Some(NodeId::new(next - 1))
                 }
+                Slot::Vacant(_) => None
+            });
+        }
+        let relink = |x: RBNode<K, V, S>| x.map(|x| moved[x.index()].expect("INVALID STATE!"));
+        self.nodes.retain(|slot| matches!(slot, Slot::Occupied(_)));
+        self.nodes.shrink_to_fit();
+        for slot in &mut self.nodes {
+            if let Slot::Occupied(node) = slot {
+                node.left = relink(node.left);
+                node.right = relink(node.right);
+                node.p = relink(node.p);
             }
-            println!("{}- {:?} {}", 
-                match rf.borrow().color {
-                    RBColor::Black => "", 
-                    RBColor::Red => "\x1b[41m"
-                }, 
-                rf.borrow().key,
-                // Rc::strong_count(rf),
-                "\x1b[0m"
-            );
-            Self::print_internal(left_node, indent + 1, NodeType::LEFT, lft_str);
         }
+        self.root = relink(self.root);
+        self.free = None;
     }
-}
 
-#[cfg(test)]
-mod fuzzer {
-    use super::*;
-    use rand;
-    use std::collections::HashSet;
+    pub fn iter(&self) -> Iter<'_, K, V, S, C> {
+        return Iter::new(self, self.get_minimum(self.root), self.get_maximum(self.root), self.len);
+    }
 
-    #[test]
-    fn test_inserts() {
-        for _ in 0..100 {
-            let mut tree = RBTree::<i32>::new();
-            for _ in 0..100 {
-                tree.insert(rand::random::<i32>());
-                assert!(tree.is_rb_tree());
-            }
+    pub fn keys(&self) -> Keys<'_, K, V, S, C> {
+        return Keys {inner: self.iter()};
+    }
+
+    pub fn values(&self) -> Values<'_, K, V, S, C> {
+        return Values {inner: self.iter()};
+    }
+
+    pub fn into_keys(self) -> IntoKeys<K, V, S, C> {
+        return IntoKeys {inner: self.into_iter()};
+    }
+
+    pub fn into_values(self) -> IntoValues<K, V, S, C> {
+        return IntoValues {inner: self.into_iter()};
+    }
+
+    /* Returns: the entry with the `k`-th smallest key (counting from 0), or None
+     * if `k >= len()`. Uses the subtree sizes, so it costs O(log n). */
+    pub fn select(&self, k: usize) -> Option<(&K, &V)> {
+        return self.select_node(k).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the entry with the smallest key, in O(log n). */
+    pub fn first_key_value(&self) -> Option<(&K, &V)> {
+        return self.get_minimum(self.root).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the entry with the largest key, in O(log n). */
+    pub fn last_key_value(&self) -> Option<(&K, &V)> {
+        return self.get_maximum(self.root).map(|x| self.key_value(x));
+    }
+
+    fn select_node(&self, mut k: usize) -> RBNode<K, V, S> {
+        let mut x = self.root;
+        while x.is_some() {
+            let left_size = self.get_size(self.get_left(x));
+            if k < left_size {
+                x = self.get_left(x);
+            } else if k == left_size {
+                return x;
+            } else {
+                k -= left_size + 1;
+                x = self.get_right(x);
+            }
+        }
+        return None;
+    }
+}
+
+/* Ordered queries. Every key comparison goes through the map's comparator. */
+impl<K, V, S, C: Comparator<K>> RBMap<K, V, S, C> {
+    fn compare(&self, a: &K, b: &K) -> Ordering {
+        return self.cmp.compare(a, b);
+    }
+
+    /* Returns: an iterator over the entries whose keys fall in `range`. Both ends
+     * are found with a single descent each, so the cost is O(log n + k). */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> Range<'_, K, V, S, C> {
+        let front = self.lower_bound_node(range.start_bound());
+        let back = self.upper_bound_node(range.end_bound());
+        let empty = match (front, back) {
+            (Some(front), Some(back)) => self.compare(&self.node(front).key, &self.node(back).key) == Ordering::Greater,
+            _ => true
+        };
+        return Range::new(self, front, back, empty);
+    }
+
+    /* Returns: the entry with the largest key less than or equal to `key`. */
+    pub fn floor(&self, key: &K) -> Option<(&K, &V)> {
+        return self.upper_bound_node(Bound::Included(key)).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the entry with the smallest key greater than or equal to `key`. */
+    pub fn ceiling(&self, key: &K) -> Option<(&K, &V)> {
+        return self.lower_bound_node(Bound::Included(key)).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the entry with the largest key strictly less than `key`. */
+    pub fn lower(&self, key: &K) -> Option<(&K, &V)> {
+        return self.upper_bound_node(Bound::Excluded(key)).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the entry with the smallest key strictly greater than `key`. */
+    pub fn higher(&self, key: &K) -> Option<(&K, &V)> {
+        return self.lower_bound_node(Bound::Excluded(key)).map(|x| self.key_value(x));
+    }
+
+    /* Returns: the number of keys strictly less than `key`, whether or not
+     * `key` itself is present. */
+    pub fn rank(&self, key: &K) -> usize {
+        return self.count_below(key, false);
+    }
+
+    /* Returns: the number of keys that fall in `range`, in O(log n). */
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        let before = match range.start_bound() {
+            Bound::Included(key) => self.count_below(key, false),
+            Bound::Excluded(key) => self.count_below(key, true),
+            Bound::Unbounded => 0
+        };
+        let through = match range.end_bound() {
+            Bound::Included(key) => self.count_below(key, true),
+            Bound::Excluded(key) => self.count_below(key, false),
+            Bound::Unbounded => self.len
+        };
+        return through.saturating_sub(before);
+    }
+
+    /* Returns: the number of keys less than `key`, or less than or equal to it
+     * when `inclusive` is set. */
+    fn count_below(&self, key: &K, inclusive: bool) -> usize {
+        let mut count = 0;
+        let mut x = self.root;
+        while let Some(id) = x {
+            let node = self.node(id);
+            let below = match self.compare(&node.key, key) {
+                Ordering::Less => true,
+                Ordering::Equal => inclusive,
+                Ordering::Greater => false
+            };
+            if below {
+                count += 1 + self.get_size(node.left);
+                x = node.right;
+            } else {
+                x = node.left;
+            }
+        }
+        return count;
+    }
+
+    /* Returns: the node with the smallest key that satisfies `bound` as a lower
+     * bound, or None if every key is below it. */
+    fn lower_bound_node(&self, bound: Bound<&K>) -> RBNode<K, V, S> {
+        let mut candidate: RBNode<K, V, S> = None;
+        let mut x = self.root;
+        while let Some(id) = x {
+            let node = self.node(id);
+            if summary::above_lower(&self.cmp, &node.key, bound) {
+                candidate = x;
+                x = node.left;
+            } else {
+                x = node.right;
+            }
+        }
+        return candidate;
+    }
+
+    /* Returns: the node with the largest key that satisfies `bound` as an upper
+     * bound, or None if every key is above it. */
+    fn upper_bound_node(&self, bound: Bound<&K>) -> RBNode<K, V, S> {
+        let mut candidate: RBNode<K, V, S> = None;
+        let mut x = self.root;
+        while let Some(id) = x {
+            let node = self.node(id);
+            if summary::below_upper(&self.cmp, &node.key, bound) {
+                candidate = x;
+                x = node.right;
+            } else {
+                x = node.left;
+            }
+        }
+        return candidate;
+    }
+
+    /* Descends from the root towards `key`. Returns: Ok with the node holding
+     * `key`, or Err with the node that a new node for `key` should hang off
+     * (None when the tree is empty). */
+    fn find_insert_position(&self, key: &K) -> Result<NodeId<K, V, S>, RBNode<K, V, S>> {
+        return self.find_insert_position_under(self.root, key);
+    }
+
+    /* As `find_insert_position`, but descending from `x`, whose subtree must
+     * span the place of `key`. */
+    fn find_insert_position_under(&self, mut x: RBNode<K, V, S>, key: &K) -> Result<NodeId<K, V, S>, RBNode<K, V, S>> {
+        let mut y: RBNode<K, V, S> = None;
+        while let Some(id) = x {
+            let node = self.node(id);
+            y = x;
+            match self.compare(key, &node.key) {
+                Ordering::Less => x = node.left,
+                Ordering::Equal => return Ok(id),
+                Ordering::Greater => x = node.right
+            }
+        }
+        return Err(y);
+    }
+
+    /* As `find_insert_position`, but searching outwards from `hint` (or from
+     * the root when it is None). `around` holds the hint's neighbours when
+     * they are known, which saves climbing to them. A key that lands next to
+     * the hint costs two comparisons. Otherwise the search climbs from the
+     * hint's neighbour to the first ancestor that bounds the key and descends
+     * from there, so its cost is the height of the lowest subtree holding
+     * both: O(log d) for a key d entries away in the usual case, and never
+     * worse than twice a search from the root. Returns: as
+     * `find_insert_position`, along with the new node's neighbours when the
+     * key lands next to the hint. */
+    fn find_insert_position_from(&self, hint: RBNode<K, V, S>, around: Option<Gap<K, V, S>>, key: &K) -> Position<K, V, S> {
+        let Some(h) = hint else {
+            return self.find_insert_position(key).map_err(|y| (y, None));
+        };
+        let side = self.compare(key, &self.node(h).key);
+        if side == Ordering::Equal {
+            return Ok(h);
+        }
+        let neighbour = match (around, side) {
+            (Some((_, next)), Ordering::Greater) => next,
+            (Some((prev, _)), _) => prev,
+            (None, Ordering::Greater) => self.get_successor(hint),
+            (None, _) => self.get_predecessor(hint)
+        };
+        let gap = if side == Ordering::Greater {(hint, neighbour)} else {(neighbour, hint)};
+        let Some(n) = neighbour else {
+            return Err((hint, Some(gap)));
+        };
+        match self.compare(key, &self.node(n).key) {
+            Ordering::Equal => return Ok(n),
+            order if order != side => {
+                /* The key falls between two adjacent nodes, one of which has
+                 * a free link facing the other. */
+                let free = if side == Ordering::Greater {self.get_right(hint)} else {self.get_left(hint)};
+                return Err((if free.is_none() {hint} else {neighbour}, Some(gap)));
+            }
+            _ => {}
+        }
+        let mut x = n;
+        while let Some(p) = self.get_parent(Some(x)) {
+            /* Only an ancestor entered from the side away from the key can
+             * bound it. */
+            let from_left = self.get_left(Some(p)) == Some(x);
+            if from_left == (side == Ordering::Greater) {
+                match self.compare(key, &self.node(p).key) {
+                    Ordering::Equal => return Ok(p),
+                    order if order != side => break,
+                    _ => {}
+                }
+            }
+            x = p;
+        }
+        return self.find_insert_position_under(Some(x), key).map_err(|y| (y, None));
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K> + Default> Default for RBTree<K, S, C> {
+    fn default() -> Self {
+        RBTree {map: RBMap::default()}
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug> RBTree<K> {
+    pub fn new() -> RBTree<K> {
+        RBTree {map: RBMap::new()}
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug, S: Summary<K, ()>> RBTree<K, S> {
+    /* Returns: an empty set that maintains a summary of type `S`. */
+    pub fn new_augmented() -> RBTree<K, S> {
+        RBTree {map: RBMap::new_augmented()}
+    }
+}
+
+impl<K: Debug, C: Comparator<K>> RBTree<K, (), C> {
+    /* Returns: an empty set that orders its keys by `cmp`, e.g.
+     * `RBTree::with_comparator(|a: &u32, b: &u32| b.cmp(a))` for descending
+     * order. */
+    pub fn with_comparator(cmp: C) -> RBTree<K, (), C> {
+        RBTree {map: RBMap::with_comparator(cmp)}
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>> RBTree<K, S, C> {
+    /* Returns: an empty set that orders its keys by `cmp` and maintains a
+     * summary of type `S`. */
+    pub fn augmented_with_comparator(cmp: C) -> RBTree<K, S, C> {
+        RBTree {map: RBMap::augmented_with_comparator(cmp)}
+    }
+
+    /* Returns: whether `key` was added. A key that is already present is
+     * left in place, as with `BTreeSet::insert`. */
+    pub fn insert(&mut self, key: K) -> bool {
+        return self.map.insert(key, ()).is_none();
+    }
+
+    /* As `insert`, but running out of memory or node indices is reported
+     * instead of panicking. */
+    pub fn checked_insert(&mut self, key: K) -> Result<bool, InsertError> {
+        return self.map.checked_insert(key, ()).map(|old| old.is_none());
+    }
+
+    /* Adds `key`, replacing an equal key if there is one. Returns: the
+     * replaced key. */
+    pub fn replace(&mut self, key: K) -> Option<K> {
+        return self.map.replace(key, ()).map(|(key, _)| key);
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return self.map.contains_key(key);
+    }
+
+    /* Returns: whether `key` was present. */
+    pub fn remove(&mut self, key: &K) -> bool {
+        return self.map.remove(key).is_some();
+    }
+
+    /* Removes `key`. Returns: the stored key that was equal to it. */
+    pub fn take(&mut self, key: &K) -> Option<K> {
+        return self.map.remove_entry(key).map(|(key, _)| key);
+    }
+
+    /* Returns: the removed key, or None if `z` is None. */
+    pub fn remove_node(&mut self, z: &RBNode<K, (), S>) -> Option<K> {
+        return z.map(|z| self.map.take_node(z).0);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.map.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.map.is_empty();
+    }
+
+    /* Hands back the memory of removed keys; see `RBMap::shrink_to_fit`. */
+    pub fn shrink_to_fit(&mut self) {
+        self.map.shrink_to_fit();
+    }
+
+    /* Returns: an iterator over the keys in ascending order. */
+    pub fn iter(&self) -> Keys<'_, K, (), S, C> {
+        return self.map.keys();
+    }
+
+    pub fn first(&self) -> Option<&K> {
+        return self.map.first_key_value().map(|(key, _)| key);
+    }
+
+    pub fn last(&self) -> Option<&K> {
+        return self.map.last_key_value().map(|(key, _)| key);
+    }
+
+    pub fn pop_first(&mut self) -> Option<K> {
+        return self.map.pop_first().map(|(key, _)| key);
+    }
+
+    pub fn pop_last(&mut self) -> Option<K> {
+        return self.map.pop_last().map(|(key, _)| key);
+    }
+
+    /* Returns: an iterator that removes and yields the keys for which `pred`
+     * returns true, as `RBMap::extract_if`. */
+    pub fn extract_if<F: FnMut(&K) -> bool>(&mut self, pred: F) -> ExtractKeysIf<'_, K, S, C, F> {
+        let next = self.map.get_minimum(self.map.root);
+        return ExtractKeysIf {map: &mut self.map, next, pred};
+    }
+
+    /* Removes every key for which `keep` returns false. */
+    pub fn retain<F: FnMut(&K) -> bool>(&mut self, mut keep: F) {
+        self.map.retain(|key, _| keep(key));
+    }
+
+    /* Returns: the largest key less than or equal to `key`. */
+    pub fn floor(&self, key: &K) -> Option<&K> {
+        return self.map.floor(key).map(|(key, _)| key);
+    }
+
+    /* Returns: the smallest key greater than or equal to `key`. */
+    pub fn ceiling(&self, key: &K) -> Option<&K> {
+        return self.map.ceiling(key).map(|(key, _)| key);
+    }
+
+    /* Returns: the largest key strictly less than `key`. */
+    pub fn lower(&self, key: &K) -> Option<&K> {
+        return self.map.lower(key).map(|(key, _)| key);
+    }
+
+    /* Returns: the smallest key strictly greater than `key`. */
+    pub fn higher(&self, key: &K) -> Option<&K> {
+        return self.map.higher(key).map(|(key, _)| key);
+    }
+
+    /* Returns: the `k`-th smallest key (counting from 0), in O(log n). */
+    pub fn select(&self, k: usize) -> Option<&K> {
+        return self.map.select(k).map(|(key, _)| key);
+    }
+
+    /* Returns: the number of keys strictly less than `key`. */
+    pub fn rank(&self, key: &K) -> usize {
+        return self.map.rank(key);
+    }
+
+    /* Returns: the number of keys that fall in `range`, in O(log n). */
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        return self.map.count_range(range);
+    }
+
+    /* Returns: an iterator over the keys that fall in `range`, in ascending order. */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> RangeKeys<'_, K, (), S, C> {
+        return RangeKeys {inner: self.map.range(range)};
+    }
+
+    /* Returns: the summary of every key in the set. */
+    pub fn summary(&self) -> S {
+        return self.map.summary();
+    }
+
+    /* Returns: the summary of the keys that fall in `range`, in O(log n). */
+    pub fn fold_range<R: RangeBounds<K>>(&self, range: R) -> S {
+        return self.map.fold_range(range);
+    }
+
+    #[allow(dead_code)]
+    fn is_rb_tree(&self) -> bool {
+        return self.map.is_rb_tree();
+    }
+
+    #[cfg(any(feature = "std", test))]
+    #[allow(dead_code)]
+    fn print(&self) {
+        self.map.print();
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K> + Default> Default for RBMap<K, V, S, C> {
+    fn default() -> Self {
+        Self::augmented_with_comparator(C::default())
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug, V> RBMap<K, V> {
+    pub fn new() -> RBMap<K, V> {
+        RBMap::with_comparator(NaturalOrder)
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug, V, S: Summary<K, V>> RBMap<K, V, S> {
+    /* Returns: an empty map that maintains a summary of type `S`. */
+    pub fn new_augmented() -> RBMap<K, V, S> {
+        RBMap::augmented_with_comparator(NaturalOrder)
+    }
+}
+
+impl<K: Debug, V, C: Comparator<K>> RBMap<K, V, (), C> {
+    /* Returns: an empty map that orders its keys by `cmp`. */
+    pub fn with_comparator(cmp: C) -> RBMap<K, V, (), C> {
+        RBMap::augmented_with_comparator(cmp)
+    }
+
+    /* Values can only be borrowed mutably when there is no summary to keep up
+     * to date; maps with a summary go through `modify` instead. */
+    pub fn get_mut(&mut self, key: &K) -> Option<&mut V> {
+        let x = self.find(key)?;
+        return Some(&mut self.node_mut(x).value);
+    }
+
+    /* Returns: the entry for `key`, found with a single descent. Inserting
+     * through a vacant entry reuses the position found by that descent. */
+    pub fn entry(&mut self, key: K) -> Entry<'_, K, V, C> {
+        match self.find_insert_position(&key) {
+            Ok(node) => Entry::Occupied(OccupiedEntry {map: self, node}),
+            Err(parent) => Entry::Vacant(VacantEntry {map: self, key, parent})
+        }
+    }
+}
+
+impl<K: Debug, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Returns: an empty map that orders its keys by `cmp` and maintains a
+     * summary of type `S`. */
+    pub fn augmented_with_comparator(cmp: C) -> RBMap<K, V, S, C> {
+        RBMap {nodes: Vec::new(), free: None, root: None, len: 0, cmp}
+    }
+
+    /* Inserts `value` under `key`. If the key is already present its value is
+     * replaced (the stored key is kept) and the old value is returned. */
+    pub fn insert(&mut self, key: K, value: V) -> Option<V> {
+        // println!("Inserting {:?}!", key);
+        // self.print();
+        match self.find_insert_position(&key) {
+            Ok(x) => return Some(self.replace_value(x, value)),
+            Err(y) => {
+                self.insert_at(y, key, value);
+                return None;
+            }
+        }
+    }
+
+    /* As `insert`, but running out of memory or node indices is reported
+     * instead of panicking. On error the map is unchanged and `key` and
+     * `value` are dropped. */
+    pub fn checked_insert(&mut self, key: K, value: V) -> Result<Option<V>, InsertError> {
+        match self.find_insert_position(&key) {
+            Ok(x) => return Ok(Some(self.replace_value(x, value))),
+            Err(y) => {
+                self.reserve_node()?;
+                self.insert_at(y, key, value);
+                return Ok(None);
+            }
+        }
+    }
+
+    /* As `insert`, but an equal key already present is replaced along with
+     * its value. Returns: the replaced entry. */
+    pub fn replace(&mut self, key: K, value: V) -> Option<(K, V)> {
+        match self.find_insert_position(&key) {
+            Ok(x) => {
+                let node = self.node_mut(x);
+                let old = (core::mem::replace(&mut node.key, key), core::mem::replace(&mut node.value, value));
+                self.update_nodes_to_root(Some(x));
+                return Some(old);
+            }
+            Err(y) => {
+                self.insert_at(y, key, value);
+                return None;
+            }
+        }
+    }
+
+    fn replace_value(&mut self, x: NodeId<K, V, S>, value: V) -> V {
+        let old = core::mem::replace(&mut self.node_mut(x).value, value);
+        self.update_nodes_to_root(Some(x));
+        return old;
+    }
+
+    /* As `insert`, but searching from `hint` with `find_insert_position_from`
+     * and only marking the changed path, so that a run of nearby inserts
+     * costs O(1) amortized each. Sizes and summaries stay stale until
+     * `settle` is called. */
+    fn insert_from(&mut self, hint: RBNode<K, V, S>, around: Option<Gap<K, V, S>>, key: K, value: V) -> Inserted<K, V, S> {
+        match self.find_insert_position_from(hint, around, &key) {
+            Ok(x) => {
+                let old = core::mem::replace(&mut self.node_mut(x).value, value);
+                self.mark_to_root(Some(x));
+                return (x, Some(old), if Some(x) == hint {around} else {None});
+            }
+            Err((y, gap)) => {
+                let goes_left = y.is_some_and(|y| self.compare(&key, &self.node(y).key) == Ordering::Less);
+                let z = self.hang(y, goes_left, key, value);
+                self.mark_to_root(Some(z));
+                self.insert_fixup(Some(z));
+                return (z, None, gap);
+            }
+        }
+    }
+
+    /* Inserts a new node for `key` even if equal keys are already present. It
+     * goes after all of them, so equal keys stay in insertion order. */
+    fn insert_duplicate(&mut self, key: K, value: V) -> NodeId<K, V, S> {
+        let mut y: RBNode<K, V, S> = None;
+        let mut x: RBNode<K, V, S> = self.root;
+        while let Some(id) = x {
+            let node = self.node(id);
+            y = x;
+            x = if self.compare(&key, &node.key) == Ordering::Less {node.left} else {node.right};
+        }
+        return self.insert_at(y, key, value);
+    }
+
+    /* Attaches a new node under `y` (as found by `find_insert_position`) and
+     * rebalances. Returns: the new node. */
+    fn insert_at(&mut self, y: RBNode<K, V, S>, key: K, value: V) -> NodeId<K, V, S> {
+        let goes_left = match y {
+            None => false,
+            Some(y) => self.compare(&key, &self.node(y).key) == Ordering::Less
+        };
+        return self.attach(y, goes_left, key, value);
+    }
+
+    /* Hangs a new node off the free `goes_left` or right link of `y` (or makes
+     * it the root when `y` is None) and rebalances. Returns: the new node. */
+    fn attach(&mut self, y: RBNode<K, V, S>, goes_left: bool, key: K, value: V) -> NodeId<K, V, S> {
+        self.settle();
+        let z = self.hang(y, goes_left, key, value);
+        self.update_nodes_to_root(y);
+        self.insert_fixup(Some(z));
+        return z;
+    }
+
+    /* Links a new red node in as `attach` does, without updating its
+     * ancestors or rebalancing. Returns: the new node. */
+    fn hang(&mut self, y: RBNode<K, V, S>, goes_left: bool, key: K, value: V) -> NodeId<K, V, S> {
+        let z = self.allocate(RBNodeInternal {
+            color: RBColor::Red,
+            summary: S::from_entry(&key, &value),
+            dirty: false,
+            key,
+            value,
+            size: 1,
+            right: None,
+            left: None,
+            p: y
+        });
+        if y.is_none() {
+            self.root = Some(z);
+        } else if goes_left {
+            self.set_left(y, Some(z));
+        } else {
+            self.set_right(y, Some(z));
+        }
+        self.len += 1;
+        return z;
+    }
+
+/*
+RB-Insert-fixup(T,z)
+  while color[p[z]] = RED {
+    if p[z] == left[p[p[z]]] {
+         y = right[p[p[z]]]
+         if color[y] = RED {
+             color[p[z]] = BLACK
+             color[y] = BLACK
+             color[p[p[z]]] = RED
+             z = p[p[z]]
+         }
+         else {
+             if z = right[p[z]] {
+                  z = p[z]
+                  LEFT-Rotate(T,z)
+             }
+             color[p[z]] = BLACK
+             color[p[p[z]]] = RED
+             RIGHT-Rotate(T,p[p[z]])
+         }
+    }
+    else {
+         y = left[p[p[z]]]
+         if color[y] = RED {
+             color[p[z]] = BLACK
+             color[y] = BLACK
+             color[p[p[z]]] = RED
+             z = p[p[z]]
+         }
+         else
+             {
+             if z = left[p[z]] {
+                  z = p[z]
+                  RIGHT-Rotate(T,z)
+             }
+             color[p[z]] = BLACK
+             color[p[p[z]]] = RED
+             LEFT-Rotate(T,p[p[z]])
+         }
+    }
+    color[root[T]] = BLACK
+  }
+*/
+
+    fn insert_fixup(&mut self, mut z: RBNode<K, V, S>) {
+        while self.get_color(self.get_parent(z)) == RBColor::Red {
+            // println!("\nAttempted fixup for where k={:?}.", get_key(&z).unwrap());
+            // self.print();
+            let z_parent = self.get_parent(z);
+            let z_grandparent = self.get_parent(z_parent);
+            if z_parent == self.get_left(z_grandparent) {
+                /* If the parent is to the left of the grandparent */
+                /* y is z's uncle */
+                let y = self.get_right(z_grandparent);
+                if self.get_color(y) == RBColor::Red {
+                    self.set_color(z_parent, RBColor::Black);
+                    self.set_color(y, RBColor::Black);
+                    self.set_color(z_grandparent, RBColor::Red);
+                    z = z_grandparent;
+                } else {
+                    if z == self.get_right(z_parent) {
+                        z = z_parent;
+                        self.left_rotate(z);
+                    }
+                    let z_parent = self.get_parent(z);
+                    let z_grandparent = self.get_parent(z_parent);
+                    self.set_color(z_parent, RBColor::Black);
+                    self.set_color(z_grandparent, RBColor::Red);
+                    self.right_rotate(z_grandparent);
+                }
+            } else {
+                /* If the parent is to the right of the grandparent */
+                let y = self.get_left(z_grandparent);
+                if self.get_color(y) == RBColor::Red {
+                    self.set_color(z_parent, RBColor::Black);
+                    self.set_color(y, RBColor::Black);
+                    self.set_color(z_grandparent, RBColor::Red);
+                    z = z_grandparent;
+                } else {
+                    if z == self.get_left(z_parent) {
+                        z = z_parent;
+                        self.right_rotate(z);
+                    }
+                    let z_parent = self.get_parent(z);
+                    let z_grandparent = self.get_parent(z_parent);
+                    self.set_color(z_parent, RBColor::Black);
+                    self.set_color(z_grandparent, RBColor::Red);
+                    self.left_rotate(z_grandparent);
+                }
+            }
+        }
+        self.set_color(self.root, RBColor::Black);
+    }
+
+    pub fn contains_key(&self, key: &K) -> bool {
+        return self.find(key).is_some();
+    }
+
+    pub fn get(&self, key: &K) -> Option<&V> {
+        return self.find(key).map(|x| &self.node(x).value);
+    }
+
+    /* Calls `f` on the value stored under `key` and then brings the summaries
+     * on the path to the root up to date. Returns: what `f` returned, or None
+     * if the key is missing. */
+    pub fn modify<R, F: FnOnce(&mut V) -> R>(&mut self, key: &K, f: F) -> Option<R> {
+        let x = self.find(key)?;
+        let result = f(&mut self.node_mut(x).value);
+        self.update_nodes_to_root(Some(x));
+        return Some(result);
+    }
+
+    fn find(&self, key: &K) -> RBNode<K, V, S> {
+        let mut x = self.root;
+        while let Some(id) = x {
+            let node = self.node(id);
+            match self.compare(key, &node.key) {
+                Ordering::Less => x = node.left,
+                Ordering::Equal => return x,
+                Ordering::Greater => x = node.right
+            }
+        }
+        return None; // x must be None
+    }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        return self.remove_entry(key).map(|(_, value)| value);
+    }
+
+    pub fn remove_entry(&mut self, key: &K) -> Option<(K, V)> {
+        let z = self.find(key)?;
+        return Some(self.take_node(z));
+    }
+
+    /* Removes the entry with the smallest key. Returns: that entry. */
+    pub fn pop_first(&mut self) -> Option<(K, V)> {
+        let x = self.get_minimum(self.root)?;
+        return Some(self.take_node(x));
+    }
+
+    /* Removes the entry with the largest key. Returns: that entry. */
+    pub fn pop_last(&mut self) -> Option<(K, V)> {
+        let x = self.get_maximum(self.root)?;
+        return Some(self.take_node(x));
+    }
+
+    /* Returns: an iterator that removes and yields, in key order, the entries
+     * for which `pred` returns true. It is lazy: entries it has not reached
+     * when it is dropped stay in the map. Values are passed by shared
+     * reference, so summaries stay correct. */
+    pub fn extract_if<F: FnMut(&K, &V) -> bool>(&mut self, pred: F) -> ExtractIf<'_, K, V, S, C, F> {
+        let next = self.get_minimum(self.root);
+        return ExtractIf {map: self, next, pred};
+    }
+
+    /* Removes every entry for which `keep` returns false. */
+    pub fn retain<F: FnMut(&K, &V) -> bool>(&mut self, mut keep: F) {
+        self.extract_if(|key, value| !keep(key, value)).for_each(drop);
+    }
+
+    /* Advances `next` through the map until `pred` accepts an entry. Returns:
+     * that entry, removed. The successor is found before the node is
+     * unlinked, and removal never moves other nodes, so `next` stays valid. */
+    pub(crate) fn extract_next<F: FnMut(&K, &V) -> bool>(&mut self, next: &mut RBNode<K, V, S>, mut pred: F) -> Option<(K, V)> {
+        while let Some(x) = *next {
+            *next = self.get_successor(Some(x));
+            let (key, value) = self.key_value(x);
+            if pred(key, value) {
+                return Some(self.take_node(x));
+            }
+        }
+        return None;
+    }
+
+    /* Unlinks `z` from the tree, frees its slot and returns its contents. */
+    fn take_node(&mut self, z: NodeId<K, V, S>) -> (K, V) {
+        self.remove_node(Some(z));
+        self.len -= 1;
+        let node = self.deallocate(z);
+        return (node.key, node.value);
+    }
+
+    fn remove_node(&mut self, z: RBNode<K, V, S>) {
+        if z.is_none() {return;}
+        self.settle();
+        let mut y = z;
+        let mut y_original_color = self.get_color(y);
+        let mut x_parent: RBNode<K, V, S>;
+        let x_parent_relationship: NodeChildType;
+        let x: RBNode<K, V, S>;
+        if self.get_left(z).is_none() {
+            x = self.get_right(z);
+            x_parent = self.get_parent(z);
+            x_parent_relationship = if z == self.get_left(x_parent) {
+                NodeChildType::LEFT
+            } else {
+                NodeChildType::RIGHT
+            };
+            self.transplant(z, x);
+        } else if self.get_right(z).is_none() {
+            x = self.get_left(z);
+            x_parent = self.get_parent(z);
+            x_parent_relationship = if z == self.get_left(x_parent) {
+                NodeChildType::LEFT
+            } else {
+                NodeChildType::RIGHT
+            };
+            self.transplant(z, x);
+        } else {
+            y = self.get_minimum(self.get_right(z));
+            y_original_color = self.get_color(y);
+            x = self.get_right(y);
+            x_parent = self.get_parent(y);
+            if y != self.get_right(z) { /* the minimum is farther down the tree. */
+                self.transplant(y, x);
+                self.set_right(y, self.get_right(z));
+                self.set_parent(self.get_right(y), y);
+                x_parent_relationship = NodeChildType::LEFT;
+            } else {
+                self.set_parent(x, y);
+                x_parent = y;
+                x_parent_relationship = NodeChildType::RIGHT;
+            }
+            self.transplant(z, y);
+            self.set_left(y, self.get_left(z));
+            self.set_parent(self.get_left(y), y);
+            self.set_color(y, self.get_color(z));
+        }
+        /* Every subtree that lost a node lies on the path from x_parent up. */
+        self.update_nodes_to_root(x_parent);
+        if y_original_color == RBColor::Black {
+            self.remove_fixup(x, x_parent, x_parent_relationship);
+        }
+    }
+
+    fn remove_fixup(&mut self, mut x: RBNode<K, V, S>, mut x_parent: RBNode<K, V, S>, mut x_parent_relationship: NodeChildType) {
+        while x != self.root && self.get_color(x) == RBColor::Black {
+            if x_parent_relationship == NodeChildType::LEFT {
+                let mut w = self.get_right(x_parent);
+                if self.get_color(w) == RBColor::Red {
+                    self.set_color(w, RBColor::Black);
+                    self.set_color(x_parent, RBColor::Red);
+                    self.left_rotate(x_parent); /* x_parent is still the parent of x. */
+                    w = self.get_right(x_parent);
+                }
+                if self.get_color(self.get_left(w)) == RBColor::Black && self.get_color(self.get_right(w)) == RBColor::Black {
+                    self.set_color(w, RBColor::Red);
+                    x = x_parent;
+                    x_parent = self.get_parent(x);
+                    x_parent_relationship = if x == self.get_left(x_parent) {
+                        NodeChildType::LEFT
+                    } else {
+                        NodeChildType::RIGHT
+                    };
+                } else {
+                    if self.get_color(self.get_right(w)) == RBColor::Black {
+                        self.set_color(self.get_left(w), RBColor::Black);
+                        self.set_color(w, RBColor::Red);
+                        self.right_rotate(w);
+                        w = self.get_right(x_parent);
+                    }
+                    self.set_color(w, self.get_color(x_parent));
+                    self.set_color(x_parent, RBColor::Black);
+                    self.set_color(self.get_right(w), RBColor::Black);
+                    self.left_rotate(x_parent);
+                    break;
+                }
+            } else {
+                let mut w = self.get_left(x_parent);
+                if self.get_color(w) == RBColor::Red {
+                    self.set_color(w, RBColor::Black);
+                    self.set_color(x_parent, RBColor::Red);
+                    self.right_rotate(x_parent);
+                    w = self.get_left(x_parent);
+                }
+                if self.get_color(self.get_left(w)) == RBColor::Black && self.get_color(self.get_right(w)) == RBColor::Black {
+                    self.set_color(w, RBColor::Red);
+                    x = x_parent;
+                    x_parent = self.get_parent(x);
+                    x_parent_relationship = if x == self.get_left(x_parent) {
+                        NodeChildType::LEFT
+                    } else {
+                        NodeChildType::RIGHT
+                    };
+                } else {
+                    if self.get_color(self.get_left(w)) == RBColor::Black {
+                        self.set_color(self.get_right(w), RBColor::Black);
+                        self.set_color(w, RBColor::Red);
+                        self.left_rotate(w);
+                        w = self.get_left(x_parent);
+                    }
+                    self.set_color(w, self.get_color(x_parent));
+                    self.set_color(x_parent, RBColor::Black);
+                    self.set_color(self.get_left(w), RBColor::Black);
+                    self.right_rotate(x_parent);
+                    break;
+                }
+            }
+        }
+        self.set_color(x, RBColor::Black);
+    }
+
+    fn transplant(&mut self, u: RBNode<K, V, S>, v: RBNode<K, V, S>) {
+        let u_parent = self.get_parent(u);
+        if u_parent.is_none() {
+            self.root = v;
+        } else if u == self.get_left(u_parent) {
+            self.set_left(u_parent, v);
+        } else {
+            self.set_right(u_parent, v);
+        }
+        self.set_parent(v, u_parent);
+    }
+
+/*
+Left-Rotate(T,x)
+    y = right[x]
+    right[x] = left[y]
+    p[left[y]] = x
+    p[y] = p[x]
+
+
+    if p[x] == nil[T] then root[T] = y
+    else
+       if x == left[p[x]] then left[p[x]] = y
+       else
+          right[p[x]] = y
+    left[y] = x
+    p[x] = y
+*/
+    fn left_rotate(&mut self, x: RBNode<K, V, S>) {
+        if x.is_none() {
+            return;
+        }
+        let y = self.get_right(x);
+        assert!(y.is_some());
+        self.set_right(x, self.get_left(y));
+        self.set_parent(self.get_left(y), x);
+        let x_parent = self.get_parent(x);
+        self.set_parent(y, x_parent);
+
+        if x_parent.is_none() {
+            self.root = y;
+        } else if x == self.get_left(x_parent) {
+            self.set_left(x_parent, y);
+        } else {
+            self.set_right(x_parent, y);
+        }
+        self.set_left(y, x);
+        self.set_parent(x, y);
+        self.update_node(x);
+        self.update_node(y);
+    }
+
+    fn right_rotate(&mut self, x: RBNode<K, V, S>) {
+        if x.is_none() {
+            return;
+        }
+        let y = self.get_left(x);
+        assert!(y.is_some());
+        self.set_left(x, self.get_right(y));
+        self.set_parent(self.get_right(y), x);
+        let x_parent = self.get_parent(x);
+        self.set_parent(y, x_parent);
+
+        if x_parent.is_none() {
+            self.root = y;
+        } else if x == self.get_left(x_parent) {
+            self.set_left(x_parent, y);
+        } else {
+            self.set_right(x_parent, y);
+        }
+        self.set_right(y, x);
+        self.set_parent(x, y);
+        self.update_node(x);
+        self.update_node(y);
+    }
+
+    #[allow(dead_code)]
+    fn is_rb_tree(&self) -> bool {
+        return self.check(true, |_, _| true).is_ok();
+    }
+}
+
+#[cfg(test)]
+mod fuzzer {
+    use super::*;
+    use std::cmp::Reverse;
+    use std::collections::{BTreeMap, BTreeSet, HashMap};
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    /* Ordered set backends under the generic fuzzers below, each with a check
+     * of its own balancing invariant. */
+    trait Backend: OrderedSet<u32> + Default {
+        fn is_valid(&self) -> bool;
+    }
+
+    impl Backend for RBTree<u32> {
+        fn is_valid(&self) -> bool {
+            let valid = self.is_rb_tree();
+            if !valid {
+                self.print();
+            }
+            return valid;
+        }
+    }
+
+    impl Backend for AvlTree<u32> {
+        fn is_valid(&self) -> bool {
+            return self.is_avl_tree();
+        }
+    }
+
+    impl Backend for Treap<u32> {
+        fn is_valid(&self) -> bool {
+            return self.is_treap();
+        }
+    }
+
+    impl Backend for BTreeSet<u32> {
+        fn is_valid(&self) -> bool {
+            return true;
         }
     }
 
-    #[test]
-    fn test_inserts_and_delete() {
-        for test_num in 1..101 {
-            println!("Test #{} beginning.", test_num);
-            let mut tree = RBTree::<usize>::new();
-            let mut set = HashSet::<usize>::new();
+    fn inserts<T: Backend>() {
+        for _ in 0..100 {
+            let mut set = T::default();
+            for _ in 0..100 {
+                set.insert(rand::random::<u32>());
+                assert!(set.is_valid());
+            }
+        }
+    }
+
+    fn inserts_and_delete<T: Backend>() {
+        /* Seeded as in tests/differential.rs: the captured output of a failure
+         * names its seed, and `RB_TREE_SEED=<seed>` reruns just that one. */
+        let seeds: Vec<u64> = match std::env::var("RB_TREE_SEED") {
+            Ok(seed) => vec![seed.parse().expect("RB_TREE_SEED must be a number")],
+            Err(_) => (0..100).chain([rand::random()]).collect()
+        };
+        for seed in seeds {
+            println!("Seed {} beginning.", seed);
+            let mut rng = StdRng::seed_from_u64(seed);
+            let mut tree = T::default();
+            /* Ordered, so that the key picked for deletion depends on the seed alone. */
+            let mut set = BTreeSet::<u32>::new();
             for _ in 0..5000 {
-                let val = rand::random::<usize>();
-                let v2 = rand::random::<usize>();
-                match rand::random::<u32>() % 3 {
+                let val = rng.gen::<u32>();
+                let v2 = rng.gen::<usize>();
+                match rng.gen::<u32>() % 3 {
                     0 => { /* insert */
                         tree.insert(val);
                         set.insert(val);
-                        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+                        assert!(tree.is_valid(), "tree is not valid");
                     },
                     1 => { /* delete */
-                        if v2 % 3 <= 0 {continue;}
+                        if v2.is_multiple_of(3) {continue;}
                         let sz = set.len();
-                        let mut i = 0;
-                        let mut del: usize = 0;
-                        for v in set.iter() {
-                            if val % sz == i {
+                        let mut del: u32 = 0;
+                        for (i, v) in set.iter().enumerate() {
+                            if val as usize % sz == i {
                                 del = *v;
                                 break;
                             }
-                            i += 1;
                         }
                         tree.remove(&del);
                         set.remove(&del);
-                        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+                        assert!(tree.is_valid(), "tree is not valid");
                     },
                     2 => { /* exists */
+                        assert_eq!(tree.len(), set.len());
                         for v in set.iter() {
-                            assert_eq!(tree.contains(v), set.contains(v),
-                            "assert failed for tree of size {}", {tree.print(); set.len()});
+                            assert_eq!(tree.contains(v), set.contains(v), "assert failed for tree of size {}", set.len());
                         }
                     },
                     _ => unreachable!()
@@ -659,4 +1600,901 @@ mod fuzzer {
             // println!("Test ended with set.len() = {}", set.len());
         }
     }
-}
\ No newline at end of file
+
+    fn random_bound() -> Bound<u32> {
+        match rand::random::<u32>() % 3 {
+            0 => Bound::Included(rand::random::<u32>() % 300),
+            1 => Bound::Excluded(rand::random::<u32>() % 300),
+            _ => Bound::Unbounded
+        }
+    }
+
+    /* Returns: whether BTreeSet and BTreeMap would panic on `range` for
+     * starting after its end. */
+    fn is_reversed(range: (Bound<u32>, Bound<u32>)) -> bool {
+        match range {
+            (Bound::Included(s), Bound::Included(e)) => s > e,
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s >= e,
+            _ => false
+        }
+    }
+
+    fn ranges<T: Backend>() {
+        for _ in 0..50 {
+            let mut tree = T::default();
+            let mut set = BTreeSet::<u32>::new();
+            for _ in 0..200 {
+                let key = rand::random::<u32>() % 256;
+                tree.insert(key);
+                set.insert(key);
+            }
+            for _ in 0..200 {
+                let range = (random_bound(), random_bound());
+                if is_reversed(range) {
+                    /* The std collections panic on these; the trait yields nothing. */
+                    assert_eq!(tree.range(range).next(), None);
+                    continue;
+                }
+                assert!(tree.range(range).eq(set.range(range)));
+            }
+        }
+    }
+
+    macro_rules! suite {
+        ($name:ident, $set:ty) => {
+            mod $name {
+                use super::*;
+
+                #[test]
+                fn test_inserts() {
+                    inserts::<$set>();
+                }
+
+                #[test]
+                fn test_inserts_and_delete() {
+                    inserts_and_delete::<$set>();
+                }
+
+                #[test]
+                fn test_range() {
+                    ranges::<$set>();
+                }
+            }
+        };
+    }
+
+    suite!(rb_tree, RBTree<u32>);
+    suite!(avl_tree, AvlTree<u32>);
+    suite!(treap, Treap<u32>);
+    suite!(btree_set, BTreeSet<u32>);
+
+    #[test]
+    fn test_map_inserts_and_delete() {
+        for _ in 0..20 {
+            let mut tree = RBMap::<u16, u32>::new();
+            let mut map = HashMap::<u16, u32>::new();
+            for _ in 0..2000 {
+                let key = rand::random::<u16>() % 512;
+                let value = rand::random::<u32>();
+                match rand::random::<u32>() % 4 {
+                    0 => { /* insert */
+                        assert_eq!(tree.insert(key, value), map.insert(key, value));
+                        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+                    },
+                    1 => { /* delete */
+                        assert_eq!(tree.remove(&key), map.remove(&key));
+                        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+                    },
+                    2 => { /* update */
+                        if let Some(v) = tree.get_mut(&key) {
+                            *v = value;
+                        }
+                        if let Some(v) = map.get_mut(&key) {
+                            *v = value;
+                        }
+                    },
+                    3 => { /* exists */
+                        for (k, v) in map.iter() {
+                            assert!(tree.contains_key(k));
+                            assert_eq!(tree.get(k), Some(v));
+                        }
+                    },
+                    _ => unreachable!()
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_iterators() {
+        for _ in 0..50 {
+            let mut tree = RBMap::<u16, u32>::new();
+            let mut map = BTreeMap::<u16, u32>::new();
+            for _ in 0..500 {
+                let key = rand::random::<u16>() % 256;
+                if rand::random::<bool>() {
+                    tree.insert(key, key as u32);
+                    map.insert(key, key as u32);
+                } else {
+                    tree.remove(&key);
+                    map.remove(&key);
+                }
+            }
+            assert_eq!(tree.len(), map.len());
+            assert!(tree.iter().eq(map.iter()));
+            assert!(tree.iter().rev().eq(map.iter().rev()));
+            assert!(tree.keys().eq(map.keys()));
+            assert!(tree.values().eq(map.values()));
+
+            /* Alternate ends at random; both iterators must agree step by step. */
+            let mut it = tree.iter();
+            let mut expected = map.iter();
+            while expected.len() > 0 {
+                assert_eq!(it.len(), expected.len());
+                if rand::random::<bool>() {
+                    assert_eq!(it.next(), expected.next());
+                } else {
+                    assert_eq!(it.next_back(), expected.next_back());
+                }
+            }
+            assert_eq!(it.next(), None);
+            assert_eq!(it.next_back(), None);
+
+            let mut it = tree.into_iter();
+            let mut expected = map.into_iter();
+            while expected.len() > 0 {
+                assert_eq!(it.len(), expected.len());
+                if rand::random::<bool>() {
+                    assert_eq!(it.next(), expected.next());
+                } else {
+                    assert_eq!(it.next_back(), expected.next_back());
+                }
+            }
+            assert_eq!(it.next(), None);
+        }
+    }
+
+    #[test]
+    fn test_map_range() {
+        /* Map ranges, stepped from both ends at random. */
+        for _ in 0..50 {
+            let mut tree = RBMap::<u32, u32>::new();
+            let mut map = BTreeMap::<u32, u32>::new();
+            for _ in 0..200 {
+                let key = rand::random::<u32>() % 256;
+                tree.insert(key, key);
+                map.insert(key, key);
+            }
+            for _ in 0..200 {
+                let range = (random_bound(), random_bound());
+                if is_reversed(range) {
+                    assert_eq!(tree.range(range).next(), None);
+                    continue;
+                }
+                assert!(tree.range(range).eq(map.range(range)));
+                assert!(tree.range(range).rev().eq(map.range(range).rev()));
+                let mut it = tree.range(range);
+                let mut expected = map.range(range);
+                loop {
+                    let step = if rand::random::<bool>() {
+                        (it.next(), expected.next())
+                    } else {
+                        (it.next_back(), expected.next_back())
+                    };
+                    assert_eq!(step.0, step.1);
+                    if step.1.is_none() {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_entry() {
+        for _ in 0..20 {
+            let mut tree = RBMap::<u16, u32>::new();
+            let mut map = BTreeMap::<u16, u32>::new();
+            for _ in 0..2000 {
+                let key = rand::random::<u16>() % 256;
+                match rand::random::<u32>() % 3 {
+                    0 => { /* count */
+                        *tree.entry(key).or_insert(0) += 1;
+                        *map.entry(key).or_insert(0) += 1;
+                    },
+                    1 => { /* bump existing, default the rest */
+                        tree.entry(key).and_modify(|v| *v *= 2).or_default();
+                        map.entry(key).and_modify(|v| *v *= 2).or_default();
+                    },
+                    2 => { /* remove through the entry */
+                        let removed = match tree.entry(key) {
+                            Entry::Occupied(entry) => Some(entry.remove()),
+                            Entry::Vacant(_) => None
+                        };
+                        assert_eq!(removed, map.remove(&key));
+                    },
+                    _ => unreachable!()
+                }
+                assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            }
+            assert_eq!(tree.len(), map.len());
+            assert!(tree.iter().eq(map.iter()));
+        }
+    }
+
+    #[test]
+    fn test_order_statistics() {
+        for _ in 0..20 {
+            let mut tree = RBTree::<u16>::new();
+            let mut set = BTreeSet::<u16>::new();
+            for _ in 0..1000 {
+                let key = rand::random::<u16>() % 512;
+                if rand::random::<u32>().is_multiple_of(3) {
+                    tree.remove(&key);
+                    set.remove(&key);
+                } else {
+                    tree.insert(key);
+                    set.insert(key);
+                }
+                assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+
+                let k = rand::random::<usize>() % (set.len() + 1);
+                assert_eq!(tree.select(k), set.iter().nth(k));
+                let probe = rand::random::<u16>() % 512;
+                assert_eq!(tree.rank(&probe), set.range(..probe).count());
+                let (lo, hi) = (probe.min(key), probe.max(key));
+                assert_eq!(tree.count_range(lo..hi), set.range(lo..hi).count());
+                assert_eq!(tree.count_range(lo..=hi), set.range(lo..=hi).count());
+                assert_eq!(tree.count_range(lo..), set.range(lo..).count());
+            }
+        }
+    }
+
+    /* Sum of the values plus the first and last key. Combining out of order
+     * gets `first`/`last` wrong, which catches non-commutative mistakes. */
+    #[derive(Clone, PartialEq, Debug)]
+    struct Stats {
+        sum: u64,
+        first: Option<u16>,
+        last: Option<u16>
+    }
+
+    impl Summary<u16, u32> for Stats {
+        fn empty() -> Self {
+            Stats {sum: 0, first: None, last: None}
+        }
+
+        fn from_entry(key: &u16, value: &u32) -> Self {
+            Stats {sum: *value as u64, first: Some(*key), last: Some(*key)}
+        }
+
+        fn combine(left: &Self, right: &Self) -> Self {
+            Stats {sum: left.sum + right.sum, first: left.first.or(right.first), last: right.last.or(left.last)}
+        }
+    }
+
+    /* Returns: the summary of `entries`, computed the slow way. */
+    fn fold<'a>(entries: impl Iterator<Item = (&'a u16, &'a u32)>) -> Stats {
+        entries.fold(Stats::empty(), |acc, (k, v)| Stats::combine(&acc, &Stats::from_entry(k, v)))
+    }
+
+    #[test]
+    fn test_summaries() {
+        for _ in 0..20 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            let mut map = BTreeMap::<u16, u32>::new();
+            for _ in 0..1000 {
+                let key = rand::random::<u16>() % 512;
+                let value = rand::random::<u32>() % 1000;
+                match rand::random::<u32>() % 3 {
+                    0 => { /* insert */
+                        tree.insert(key, value);
+                        map.insert(key, value);
+                    },
+                    1 => { /* delete */
+                        tree.remove(&key);
+                        map.remove(&key);
+                    },
+                    2 => { /* modify */
+                        tree.modify(&key, |v| *v = value);
+                        if let Some(v) = map.get_mut(&key) {
+                            *v = value;
+                        }
+                    },
+                    _ => unreachable!()
+                }
+                assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+                assert_eq!(tree.summary(), fold(map.iter()));
+
+                let a = rand::random::<u16>() % 512;
+                let b = rand::random::<u16>() % 512;
+                let (lo, hi) = (a.min(b), a.max(b));
+                assert_eq!(tree.fold_range(lo..hi), fold(map.range(lo..hi)));
+                assert_eq!(tree.fold_range(lo..=hi), fold(map.range(lo..=hi)));
+                assert_eq!(tree.fold_range(..hi), fold(map.range(..hi)));
+                assert_eq!(tree.fold_range((Bound::Excluded(lo), Bound::Unbounded)),
+                    fold(map.range((Bound::Excluded(lo), Bound::Unbounded))));
+            }
+        }
+    }
+
+    fn random_tree(max_len: usize) -> (RBTree<u16>, BTreeSet<u16>) {
+        let mut tree = RBTree::<u16>::new();
+        let mut set = BTreeSet::<u16>::new();
+        for _ in 0..rand::random::<usize>() % (max_len + 1) {
+            let key = rand::random::<u16>() % 2048;
+            tree.insert(key);
+            set.insert(key);
+        }
+        return (tree, set);
+    }
+
+    #[test]
+    fn test_split_join() {
+        for _ in 0..200 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            let mut map = BTreeMap::<u16, u32>::new();
+            for _ in 0..rand::random::<usize>() % 1000 {
+                let key = rand::random::<u16>() % 2048;
+                let value = rand::random::<u32>() % 1000;
+                tree.insert(key, value);
+                map.insert(key, value);
+            }
+            let key = rand::random::<u16>() % 2048;
+            let (below, above) = tree.split_at(&key);
+            let map_above = map.split_off(&key);
+            for (half, expected) in [(&below, &map), (&above, &map_above)] {
+                assert!(half.is_rb_tree(), "tree is not rb tree{}", {half.print(); ""});
+                assert!(half.iter().eq(expected.iter()));
+                assert_eq!(half.summary(), fold(expected.iter()));
+            }
+            let mut joined = below.join(above);
+            assert!(joined.is_rb_tree(), "tree is not rb tree{}", {joined.print(); ""});
+            map.extend(map_above);
+            assert!(joined.iter().eq(map.iter()));
+
+            /* The arena keeps working after a join. */
+            let key = rand::random::<u16>() % 2048;
+            assert_eq!(joined.insert(key, 0), map.insert(key, 0));
+            assert_eq!(joined.remove(&key), map.remove(&key));
+            assert!(joined.is_rb_tree(), "tree is not rb tree{}", {joined.print(); ""});
+
+            /* Shrinking drops the slots left vacant and keeps every entry. */
+            let cut = rand::random::<u16>() % 2048;
+            for key in map.range(..cut).map(|(k, _)| *k).collect::<Vec<_>>() {
+                assert_eq!(joined.remove(&key), map.remove(&key));
+            }
+            joined.shrink_to_fit();
+            assert_eq!(joined.nodes.len(), map.len());
+            assert!(joined.is_rb_tree(), "tree is not rb tree{}", {joined.print(); ""});
+            assert!(joined.iter().eq(map.iter()));
+            assert_eq!(joined.summary(), fold(map.iter()));
+        }
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        for _ in 0..200 {
+            /* Mix similar and very different sizes. */
+            let (a, a_set) = random_tree(if rand::random::<bool>() {50} else {1000});
+            let (b, b_set) = random_tree(1000);
+            let expected = [
+                a_set.union(&b_set).copied().collect::<Vec<_>>(),
+                a_set.intersection(&b_set).copied().collect::<Vec<_>>(),
+                a_set.difference(&b_set).copied().collect::<Vec<_>>(),
+                b_set.difference(&a_set).copied().collect::<Vec<_>>(),
+                a_set.symmetric_difference(&b_set).copied().collect::<Vec<_>>()
+            ];
+            let copy = |tree: &RBTree<u16>| {
+                let mut copy = RBTree::<u16>::new();
+                for key in tree.iter() {
+                    copy.insert(*key);
+                }
+                copy
+            };
+            let results = [
+                copy(&a).union(copy(&b)),
+                copy(&a).intersection(copy(&b)),
+                copy(&a).difference(copy(&b)),
+                copy(&b).difference(copy(&a)),
+                a.symmetric_difference(b)
+            ];
+            for (result, expected) in results.iter().zip(expected) {
+                assert!(result.is_rb_tree(), "tree is not rb tree{}", {result.print(); ""});
+                assert_eq!(result.len(), expected.len());
+                assert_eq!(result.iter().copied().collect::<Vec<_>>(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_comparator() {
+        /* A descending map must behave like a BTreeMap over `Reverse` keys. */
+        let descending = |a: &u16, b: &u16| b.cmp(a);
+        for _ in 0..50 {
+            let mut tree = RBMap::<u16, u32, Stats, _>::augmented_with_comparator(descending);
+            let mut map = BTreeMap::<Reverse<u16>, u32>::new();
+            for _ in 0..1000 {
+                let key = rand::random::<u16>() % 512;
+                if rand::random::<u32>().is_multiple_of(3) {
+                    assert_eq!(tree.remove(&key), map.remove(&Reverse(key)));
+                } else {
+                    let value = rand::random::<u32>() % 1000;
+                    assert_eq!(tree.insert(key, value), map.insert(Reverse(key), value));
+                }
+                assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            }
+            assert!(tree.iter().map(|(k, v)| (Reverse(*k), v)).eq(map.iter().map(|(k, v)| (*k, v))));
+            for _ in 0..100 {
+                let (a, b) = (rand::random::<u16>() % 512, rand::random::<u16>() % 512);
+                let (hi, lo) = (a.max(b), a.min(b));
+                assert_eq!(tree.get(&a), map.get(&Reverse(a)));
+                assert_eq!(tree.rank(&a), map.range(..Reverse(a)).count());
+                assert_eq!(tree.count_range(hi..=lo), map.range(Reverse(hi)..=Reverse(lo)).count());
+                assert!(tree.range(hi..lo).map(|(k, _)| Reverse(*k)).eq(map.range(Reverse(hi)..Reverse(lo)).map(|(k, _)| *k)));
+                let expected = fold(map.range(Reverse(hi)..=Reverse(lo)).map(|(k, v)| (&k.0, v)));
+                assert_eq!(tree.fold_range(hi..=lo), expected);
+            }
+        }
+
+        /* Split and join keep the comparator; a plain `fn` is `Clone`. */
+        fn descending_fn(a: &u16, b: &u16) -> Ordering {
+            b.cmp(a)
+        }
+        let mut tree = RBTree::with_comparator(descending_fn as fn(&u16, &u16) -> Ordering);
+        for key in 0..100 {
+            tree.insert(key);
+        }
+        let (below, above) = tree.split_at(&40);
+        assert!(below.iter().copied().eq((41..100).rev()));
+        assert!(above.iter().copied().eq((0..=40).rev()));
+        let mut joined = below.join(above);
+        joined.insert(100);
+        assert!(joined.is_rb_tree());
+        assert!(joined.iter().copied().eq((0..=100).rev()));
+    }
+
+    #[test]
+    fn test_multiset() {
+        for _ in 0..50 {
+            let mut multiset = RBMultiSet::<u16>::new();
+            let mut counts = BTreeMap::<u16, usize>::new();
+            for _ in 0..1000 {
+                let key = rand::random::<u16>() % 64;
+                match rand::random::<u32>() % 6 {
+                    0 => assert_eq!(multiset.remove_all(&key), counts.remove(&key).unwrap_or(0)),
+                    1 | 2 => {
+                        let expected = counts.get_mut(&key).map(|count| {
+                            *count -= 1;
+                            key
+                        });
+                        counts.retain(|_, count| *count > 0);
+                        assert_eq!(multiset.remove_one(&key), expected);
+                    }
+                    _ => {
+                        multiset.insert(key);
+                        *counts.entry(key).or_default() += 1;
+                    }
+                }
+                assert!(multiset.is_rb_tree(), "tree is not rb tree{}", {multiset.print(); ""});
+                assert_eq!(multiset.count(&key), counts.get(&key).copied().unwrap_or(0));
+                assert_eq!(multiset.equal_range(&key).count(), multiset.count(&key));
+                assert!(multiset.equal_range(&key).all(|k| *k == key));
+            }
+            let expected = counts.iter().flat_map(|(key, count)| std::iter::repeat_n(*key, *count)).collect::<Vec<_>>();
+            assert_eq!(multiset.len(), expected.len());
+            assert!(multiset.iter().copied().eq(expected.iter().copied()));
+            let key = rand::random::<u16>() % 64;
+            assert_eq!(multiset.rank(&key), expected.iter().filter(|k| **k < key).count());
+            assert_eq!(multiset.count_range(..key), multiset.rank(&key));
+            assert!((0..expected.len()).all(|k| multiset.select(k) == Some(&expected[k])));
+        }
+    }
+
+    #[test]
+    fn test_cursor() {
+        /* The model is a sorted vector and an index into it, where the index
+         * `len` is the ghost position. */
+        for _ in 0..100 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            let mut model = Vec::<(u16, u32)>::new();
+            for _ in 0..rand::random::<usize>() % 300 {
+                let key = rand::random::<u16>() % 1024 * 4;
+                if tree.insert(key, key as u32).is_none() {
+                    model.push((key, key as u32));
+                }
+            }
+            model.sort();
+            let start = rand::random::<u16>() % 4096;
+            let mut at = model.partition_point(|(k, _)| *k < start);
+            let mut cursor = tree.lower_bound_mut(Bound::Included(&start));
+            for _ in 0..300 {
+                let ghost = model.len();
+                match rand::random::<u32>() % 6 {
+                    0 => {
+                        cursor.move_next();
+                        at = if at == ghost {0} else {at + 1};
+                    }
+                    1 => {
+                        cursor.move_prev();
+                        at = if at == 0 {ghost} else {at - 1};
+                    }
+                    2 => {
+                        let removed = cursor.remove_current();
+                        assert_eq!(removed, (at < ghost).then(|| model.remove(at)));
+                    }
+                    3 => {
+                        /* Keys are multiples of 4 apart, so there is always a gap. */
+                        let after = if at == ghost {None} else {Some(model[at].0)};
+                        let key = match after {
+                            Some(k) if model.get(at + 1).is_none_or(|(next, _)| *next > k + 1) => k + 1,
+                            None if model.first().is_none_or(|(first, _)| *first > 0) => model.first().map_or(2, |(first, _)| first - 1),
+                            _ => continue
+                        };
+                        cursor.insert_after(key, 7);
+                        model.insert(if at == ghost {0} else {at + 1}, (key, 7));
+                        if at == ghost {
+                            at += 1;
+                        }
+                    }
+                    4 => {
+                        let before = if at == 0 {None} else {Some(model[at - 1].0)};
+                        let key = match (before, model.get(at)) {
+                            (_, Some((k, _))) if *k > 0 && before.is_none_or(|b| b + 1 < *k) => k - 1,
+                            (Some(b), None) => b + 1,
+                            (None, None) => 2,
+                            _ => continue
+                        };
+                        cursor.insert_before(key, 9);
+                        model.insert(at, (key, 9));
+                        at += 1;
+                    }
+                    _ => {
+                        let ghost = model.len();
+                        let prev = if at == ghost {model.last()} else if at == 0 {None} else {model.get(at - 1)};
+                        let next = if at == ghost {model.first()} else {model.get(at + 1)};
+                        assert_eq!(cursor.peek_prev(), prev.map(|(k, v)| (k, v)));
+                        assert_eq!(cursor.peek_next(), next.map(|(k, v)| (k, v)));
+                    }
+                }
+                assert_eq!(cursor.key_value(), model.get(at).map(|(k, v)| (k, v)));
+            }
+            drop(cursor);
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            assert!(tree.iter().map(|(k, v)| (*k, *v)).eq(model.iter().copied()));
+            assert_eq!(tree.summary(), fold(model.iter().map(|(k, v)| (k, v))));
+            let mut read = tree.cursor_back();
+            for entry in model.iter().rev() {
+                assert_eq!(read.key_value(), Some((&entry.0, &entry.1)));
+                read.move_prev();
+            }
+            assert_eq!(read.key(), None);
+        }
+    }
+
+    #[test]
+    fn test_hinted_insert() {
+        for _ in 0..100 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            let mut map = BTreeMap::<u16, u32>::new();
+            let mut cursor = tree.cursor_front_mut();
+            for _ in 0..500 {
+                let key = rand::random::<u16>() % 1024;
+                match rand::random::<u32>() % 8 {
+                    0 | 1 => {
+                        assert_eq!(cursor.seek(&key), map.contains_key(&key));
+                        assert_eq!(cursor.key_value(), map.range(key..).next());
+                    }
+                    2 => {
+                        /* Removal settles the sizes left stale by inserts. */
+                        let current = cursor.key_value().map(|(k, v)| (*k, *v));
+                        if let Some((k, _)) = current {
+                            map.remove(&k);
+                        }
+                        assert_eq!(cursor.remove_current(), current);
+                    }
+                    _ => {
+                        let value = rand::random::<u32>() % 1000;
+                        assert_eq!(cursor.insert(key, value), map.insert(key, value));
+                        assert_eq!(cursor.key_value(), Some((&key, &value)));
+                    }
+                }
+                if rand::random::<u32>().is_multiple_of(8) {
+                    cursor.move_next();
+                }
+            }
+            drop(cursor);
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            assert!(tree.iter().eq(map.iter()));
+            assert_eq!(tree.summary(), fold(map.iter()));
+        }
+
+        /* A nearly sorted stream fed through one cursor compares O(1) keys per
+         * insert, where inserting from the root compares O(log n). */
+        let count = std::cell::Cell::new(0);
+        let counting = |a: &u32, b: &u32| {
+            count.set(count.get() + 1);
+            a.cmp(b)
+        };
+        let stream = (0..10000u32).map(|i| i * 8 + rand::random::<u32>() % 12).collect::<Vec<_>>();
+        let mut tree = RBMap::with_comparator(counting);
+        let mut cursor = tree.cursor_front_mut();
+        for &key in &stream {
+            cursor.insert(key, ());
+        }
+        let hinted = count.replace(0);
+        let mut tree = RBMap::with_comparator(counting);
+        for &key in &stream {
+            tree.insert(key, ());
+        }
+        assert!(tree.is_rb_tree());
+        assert!(4 * hinted < count.get(), "{} hinted against {} plain comparisons", hinted, count.get());
+
+        /* Counting every node looked at, upkeep included, a sorted stream
+         * through one cursor costs O(1) per insert while each insert from the
+         * root costs O(log n). */
+        let keys = 0..u16::MAX;
+        let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+        VISITS.with(|visits| visits.set(0));
+        let mut cursor = tree.cursor_front_mut();
+        for key in keys.clone() {
+            cursor.insert(key, key as u32);
+        }
+        drop(cursor);
+        let hinted = VISITS.with(|visits| visits.replace(0));
+        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+        assert_eq!(tree.summary(), fold(tree.iter()));
+        let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+        for key in keys.clone() {
+            tree.insert(key, key as u32);
+        }
+        let plain = VISITS.with(|visits| visits.get());
+        assert!(3 * hinted < plain, "{} hinted against {} plain visits", hinted, plain);
+
+        /* A leaked cursor leaves its stale marks behind. Readers work through
+         * them and the next change settles them. */
+        let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+        tree.extend((0..1000).map(|k| (k * 2, 1)));
+        let mut cursor = tree.lower_bound_mut(Bound::Included(&500));
+        for k in 250..450 {
+            cursor.insert(k * 2 + 1, 2);
+        }
+        core::mem::forget(cursor);
+        let mut map = (0..1000).map(|k| (k * 2, 1)).chain((250..450).map(|k| (k * 2 + 1, 2))).collect::<BTreeMap<u16, u32>>();
+        assert_eq!(tree.validate(), Ok(()));
+        assert_eq!(tree.summary(), fold(map.iter()));
+        assert_eq!(tree.fold_range(400..700), fold(map.range(400..700)));
+        assert_eq!(tree.count_range(400..700), map.range(400..700).count());
+        for (i, (k, v)) in map.iter().enumerate().step_by(7) {
+            assert_eq!(tree.select(i), Some((k, v)));
+            assert_eq!(tree.rank(k), i);
+        }
+        assert_eq!(tree.remove(&0), map.remove(&0));
+        assert!(!tree.node(tree.root.expect("INVALID STATE!")).dirty);
+        assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+        assert_eq!(tree.summary(), fold(map.iter()));
+    }
+
+    #[test]
+    fn test_bulk_build() {
+        /* Every size up to a few complete levels, including the perfect ones. */
+        for n in 0..600u16 {
+            let mut tree: RBMap<u16, u32, Stats> = (0..n).map(|k| (k * 2, k as u32)).collect();
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            assert_eq!(tree.len(), n as usize);
+            assert!(tree.iter().map(|(k, v)| (*k, *v)).eq((0..n).map(|k| (k * 2, k as u32))));
+            assert_eq!(tree.summary(), fold(tree.iter()));
+            tree.insert(3, 3);
+            tree.remove(&0);
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+        }
+
+        /* Duplicates in sorted input keep the last value. */
+        let tree = RBMap::from_sorted_iter([(1, 'a'), (2, 'b'), (2, 'c'), (3, 'd')]);
+        assert!(tree.iter().map(|(k, v)| (*k, *v)).eq([(1, 'a'), (2, 'c'), (3, 'd')]));
+
+        /* Input made of sorted runs (some ascending past the current keys, some
+         * below them, some overlapping) must match inserting one at a time. */
+        for _ in 0..200 {
+            let mut input = Vec::new();
+            for _ in 0..rand::random::<usize>() % 8 {
+                let start = rand::random::<u16>() % 4096;
+                let step = 1 + rand::random::<u16>() % 4;
+                let len = rand::random::<u16>() % 200;
+                let mut run = (0..len).map(|i| (start.wrapping_add(i * step) % 4096, rand::random::<u32>() % 100)).collect::<Vec<_>>();
+                if rand::random::<bool>() {
+                    run.reverse();
+                }
+                input.extend(run);
+            }
+            let mut tree: RBMap<u16, u32, Stats> = input[..input.len() / 2].iter().copied().collect();
+            tree.extend(input[input.len() / 2..].iter().copied());
+            let map = input.iter().copied().collect::<BTreeMap<_, _>>();
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            assert!(tree.iter().eq(map.iter()));
+            assert_eq!(tree.summary(), fold(map.iter()));
+
+            let set: RBTree<u16> = input.iter().map(|(k, _)| *k).collect();
+            assert!(set.is_rb_tree());
+            assert!(set.iter().eq(map.keys()));
+        }
+    }
+
+    #[test]
+    fn test_render() {
+        for _ in 0..50 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            for _ in 0..rand::random::<usize>() % 300 {
+                let key = rand::random::<u16>() % 512;
+                tree.insert(key, key as u32);
+            }
+            let red = tree.nodes.iter().filter(|slot| matches!(slot, Slot::Occupied(node) if node.color == RBColor::Red)).count();
+
+            let text = tree.text().with_sizes().to_string();
+            assert!(!text.contains('\x1b'));
+            assert_eq!(text.lines().count(), tree.len());
+            assert_eq!(text.matches(" R [").count(), red);
+            assert!(text.contains(&format!("[size={}]", tree.len())) || tree.is_empty());
+
+            let dot = tree.dot().with_summaries().to_string();
+            assert_eq!(dot.matches("fillcolor=red").count(), red);
+            assert_eq!(dot.matches(" -> ").count() - dot.matches(" -> nil").count(), tree.len().saturating_sub(1));
+            assert_eq!(dot.matches("fillcolor=").count(), tree.len());
+
+            let svg = tree.svg().to_string();
+            assert_eq!(svg.matches("<circle").count(), tree.len());
+            assert_eq!(svg.matches("fill=\"red\"").count(), red);
+            assert_eq!(svg.matches("<line").count(), tree.len().saturating_sub(1));
+        }
+    }
+
+    #[test]
+    fn test_validate() {
+        /* Breaks one invariant of a perfect tree of 1..=7 and checks it is
+         * reported at the right node. */
+        fn broken(f: impl Fn(&mut RBMap<u16, u32, Stats>, [NodeId<u16, u32, Stats>; 7])) -> Violation {
+            let mut tree = RBMap::<u16, u32, Stats>::build_from_sorted(NaturalOrder, (1..=7).map(|k| (k, k as u32)));
+            assert_eq!(tree.validate(), Ok(()));
+            let root = tree.root.expect("INVALID STATE!");
+            let node = |x: NodeId<u16, u32, Stats>, side: Side| {
+                let node = tree.node(x);
+                return if side == Side::Left {node.left} else {node.right}.expect("INVALID STATE!");
+            };
+            let (l, r) = (node(root, Side::Left), node(root, Side::Right));
+            let nodes = [root, l, r, node(l, Side::Left), node(l, Side::Right), node(r, Side::Left), node(r, Side::Right)];
+            f(&mut tree, nodes);
+            return tree.validate().expect_err("tree should be invalid");
+        }
+        type Breaker = fn(&mut RBMap<u16, u32, Stats>, [NodeId<u16, u32, Stats>; 7]);
+        use Side::{Left, Right};
+        let cases: [(Breaker, Invariant, Vec<Side>); 8] = [
+            (|t, n| t.node_mut(n[0]).color = RBColor::Red, Invariant::RedRoot, vec![]),
+            (|t, n| {t.node_mut(n[1]).color = RBColor::Red; t.node_mut(n[3]).color = RBColor::Red;}, Invariant::RedRed, vec![Left, Left]),
+            (|t, n| t.node_mut(n[6]).color = RBColor::Red, Invariant::BlackHeight, vec![Right]),
+            (|t, n| t.node_mut(n[1]).key = 100, Invariant::Order, vec![Left, Right]),
+            (|t, n| t.node_mut(n[6]).p = Some(n[0]), Invariant::Parent, vec![Right, Right]),
+            (|t, n| t.node_mut(n[1]).size += 1, Invariant::Size, vec![Left]),
+            (|t, n| t.node_mut(n[5]).value = 0, Invariant::Summary, vec![Right, Left]),
+            (|t, _| t.len += 1, Invariant::Length, vec![])
+        ];
+        for (f, invariant, path) in cases {
+            assert_eq!(broken(f), Violation {invariant, path});
+        }
+        assert_eq!(broken(|t, n| t.node_mut(n[5]).p = None).to_string(), "parent link does not match at root.right.left");
+
+        /* A free list that cycles is reported rather than walked forever. */
+        let mut tree = RBMap::<u16, u32, Stats>::build_from_sorted(NaturalOrder, (1..=7).map(|k| (k, k as u32)));
+        tree.remove(&3);
+        tree.remove(&5);
+        let head = tree.free.expect("INVALID STATE!");
+        tree.nodes[head.index()] = Slot::Vacant(Some(head));
+        assert_eq!(tree.validate(), Err(Violation {invariant: Invariant::Arena, path: vec![]}));
+
+        /* Links past the end of the arena or to a vacant slot are reported
+         * at the node holding them. */
+        assert_eq!(broken(|t, n| t.node_mut(n[4]).left = Some(NodeId::new(7))), Violation {invariant: Invariant::Arena, path: vec![Left, Right, Left]});
+        assert_eq!(broken(|t, n| {t.take_node(n[6]); t.node_mut(n[2]).right = Some(n[6]);}), Violation {invariant: Invariant::Arena, path: vec![Right, Right]});
+
+        /* Multisets allow equal keys, sets do not. */
+        let mut multiset = RBMultiSet::new();
+        for _ in 0..3 {
+            multiset.insert(1);
+        }
+        assert_eq!(multiset.validate(), Ok(()));
+        let mut tree = RBTree::from_sorted_iter(1..=3);
+        let x = tree.map.root.expect("INVALID STATE!");
+        tree.map.node_mut(x).key = 1;
+        assert_eq!(tree.validate(), Err(Violation {invariant: Invariant::Order, path: vec![]}));
+    }
+
+    #[test]
+    fn test_replace_and_take() {
+        /* Keys equal by the comparator but told apart by their second field. */
+        let mut tree = RBTree::with_comparator(|a: &(u16, u16), b: &(u16, u16)| a.0.cmp(&b.0));
+        let mut model = BTreeMap::<u16, u16>::new();
+        for i in 0..2000 {
+            let key = (rand::random::<u16>() % 128, i);
+            match rand::random::<u32>() % 4 {
+                0 => assert_eq!(tree.insert(key), !model.contains_key(&key.0) && model.insert(key.0, key.1).is_none()),
+                1 => assert_eq!(tree.replace(key), model.insert(key.0, key.1).map(|old| (key.0, old))),
+                2 => assert_eq!(tree.take(&key), model.remove(&key.0).map(|old| (key.0, old))),
+                3 => assert_eq!(tree.checked_insert(key), Ok(!model.contains_key(&key.0) && model.insert(key.0, key.1).is_none())),
+                _ => unreachable!()
+            }
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+        }
+        assert!(tree.iter().copied().eq(model.into_iter()));
+
+        /* Replacing an entry updates the summaries above it. */
+        let mut map = RBMap::<u16, u32, Stats>::new_augmented();
+        map.extend((0..100).map(|k| (k, 1)));
+        assert_eq!(map.replace(50, 10), Some((50, 1)));
+        assert_eq!(map.checked_insert(60, 5), Ok(Some(1)));
+        assert_eq!(map.checked_insert(100, 1), Ok(None));
+        assert_eq!(map.summary().sum, 114);
+        assert_eq!(map.validate(), Ok(()));
+
+        /* Node removal hands back the key like `take`. */
+        let mut tree = RBTree::from_sorted_iter(0..10u16);
+        let node = tree.map.find(&4);
+        let removed = (tree.remove_node(&node), tree.remove_node(&None));
+        assert_eq!(removed, (Some(4), None));
+        assert!(!tree.contains(&4));
+    }
+
+    #[test]
+    fn test_bulk_removal() {
+        let bound = |kind: u32, key: u16| match kind % 3 {
+            0 => Bound::Included(key),
+            1 => Bound::Excluded(key),
+            _ => Bound::Unbounded
+        };
+        for _ in 0..200 {
+            let mut tree = RBMap::<u16, u32, Stats>::new_augmented();
+            let mut map = BTreeMap::<u16, u32>::new();
+            for _ in 0..rand::random::<usize>() % 500 {
+                let (key, value) = (rand::random::<u16>() % 1024, rand::random::<u32>() % 100);
+                tree.insert(key, value);
+                map.insert(key, value);
+            }
+            match rand::random::<u32>() % 3 {
+                0 => {
+                    let (a, b) = (rand::random::<u16>() % 1024, rand::random::<u16>() % 1024);
+                    let range = (bound(rand::random(), a.min(b)), bound(rand::random(), a.max(b)));
+                    if matches!(range, (Bound::Excluded(a), Bound::Excluded(b)) if a == b) {
+                        continue; /* BTreeMap::range panics on this one */
+                    }
+                    let removed = map.range(range).map(|(k, _)| *k).collect::<Vec<_>>();
+                    assert_eq!(tree.remove_range(range), removed.len());
+                    for k in removed {
+                        map.remove(&k);
+                    }
+                }
+                1 => {
+                    let m = 2 + rand::random::<u32>() % 5;
+                    tree.retain(|_, v| v % m != 0);
+                    map.retain(|_, v| *v % m != 0);
+                }
+                _ => {
+                    let n = rand::random::<usize>() % 50;
+                    let extracted = tree.extract_if(|k, _| k % 3 == 0).take(n).collect::<Vec<_>>();
+                    let want = map.iter().filter(|(k, _)| *k % 3 == 0).take(n).map(|(k, v)| (*k, *v)).collect::<Vec<_>>();
+                    assert_eq!(extracted, want);
+                    for (k, _) in want {
+                        map.remove(&k);
+                    }
+                }
+            }
+            assert!(tree.is_rb_tree(), "tree is not rb tree{}", {tree.print(); ""});
+            assert_eq!(tree.validate(), Ok(()));
+            assert!(tree.iter().eq(map.iter()));
+            assert_eq!(tree.summary(), fold(map.iter()));
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "ascending")]
+    fn test_bulk_build_unsorted() {
+        RBTree::from_sorted_iter([1, 3, 2]);
+    }
+}
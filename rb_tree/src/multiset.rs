@@ -0,0 +1,161 @@
+use core::fmt::Debug;
+use core::ops::{Bound, RangeBounds};
+
+use crate::{Comparator, IntoKeys, Keys, NaturalOrder, RBMap, RangeKeys, Summary, Violation};
+
+/* An ordered multiset. Unlike `RBTree`, inserting a key that is already
+ * present adds another copy of it. Each copy is its own node, so keys that
+ * compare equal but differ otherwise (events at the same time, say) are all
+ * kept, in the order they were inserted. Every count is taken from subtree
+ * sizes, so `count` and `len` include multiplicity. */
+pub struct RBMultiSet<K, S = (), C = NaturalOrder> {
+    map: RBMap<K, (), S, C>
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K> + Default> Default for RBMultiSet<K, S, C> {
+    fn default() -> Self {
+        RBMultiSet {map: RBMap::default()}
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug> RBMultiSet<K> {
+    pub fn new() -> RBMultiSet<K> {
+        RBMultiSet {map: RBMap::new()}
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Debug, S: Summary<K, ()>> RBMultiSet<K, S> {
+    /* Returns: an empty multiset that maintains a summary of type `S`. */
+    pub fn new_augmented() -> RBMultiSet<K, S> {
+        RBMultiSet {map: RBMap::new_augmented()}
+    }
+}
+
+impl<K: Debug, C: Comparator<K>> RBMultiSet<K, (), C> {
+    /* Returns: an empty multiset that orders its keys by `cmp`. */
+    pub fn with_comparator(cmp: C) -> RBMultiSet<K, (), C> {
+        RBMultiSet {map: RBMap::with_comparator(cmp)}
+    }
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>> RBMultiSet<K, S, C> {
+    /* Returns: an empty multiset that orders its keys by `cmp` and maintains
+     * a summary of type `S`. */
+    pub fn augmented_with_comparator(cmp: C) -> RBMultiSet<K, S, C> {
+        RBMultiSet {map: RBMap::augmented_with_comparator(cmp)}
+    }
+
+    /* Adds one copy of `key`, after any equal keys already present. */
+    pub fn insert(&mut self, key: K) {
+        self.map.insert_duplicate(key, ());
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return self.map.contains_key(key);
+    }
+
+    /* Returns: the number of copies of `key`, in O(log n). */
+    pub fn count(&self, key: &K) -> usize {
+        return self.map.count_range((Bound::Included(key), Bound::Included(key)));
+    }
+
+    /* Returns: an iterator over every copy of `key`, in insertion order. */
+    pub fn equal_range(&self, key: &K) -> RangeKeys<'_, K, (), S, C> {
+        return RangeKeys {inner: self.map.range((Bound::Included(key), Bound::Included(key)))};
+    }
+
+    /* Removes the earliest inserted copy of `key`. Returns: that copy, or None
+     * if `key` is missing. */
+    pub fn remove_one(&mut self, key: &K) -> Option<K> {
+        let x = self.map.lower_bound_node(Bound::Included(key))?;
+        if self.map.compare(&self.map.node(x).key, key) != core::cmp::Ordering::Equal {
+            return None;
+        }
+        return Some(self.map.take_node(x).0);
+    }
+
+    /* Removes every copy of `key` by cutting them out with `remove_range`, in
+     * O(log n) plus O(1) per copy. Returns: how many there were. */
+    pub fn remove_all(&mut self, key: &K) -> usize {
+        return self.map.remove_range((Bound::Included(key), Bound::Included(key)));
+    }
+
+    /* Returns: the number of keys, counting every copy. */
+    pub fn len(&self) -> usize {
+        return self.map.len();
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.map.is_empty();
+    }
+
+    /* Returns: an iterator over the keys in ascending order, equal keys in
+     * insertion order. */
+    pub fn iter(&self) -> Keys<'_, K, (), S, C> {
+        return self.map.keys();
+    }
+
+    /* Returns: the `k`-th smallest key (counting from 0, with multiplicity). */
+    pub fn select(&self, k: usize) -> Option<&K> {
+        return self.map.select(k).map(|(key, _)| key);
+    }
+
+    /* Returns: the number of keys strictly less than `key`, with multiplicity. */
+    pub fn rank(&self, key: &K) -> usize {
+        return self.map.rank(key);
+    }
+
+    /* Returns: the number of keys that fall in `range`, with multiplicity. */
+    pub fn count_range<R: RangeBounds<K>>(&self, range: R) -> usize {
+        return self.map.count_range(range);
+    }
+
+    /* Returns: an iterator over the keys that fall in `range`, in ascending order. */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> RangeKeys<'_, K, (), S, C> {
+        return RangeKeys {inner: self.map.range(range)};
+    }
+
+    /* Returns: the summary of every key, counting every copy. */
+    pub fn summary(&self) -> S {
+        return self.map.summary();
+    }
+
+    /* Returns: the summary of the keys that fall in `range`, in O(log n). */
+    pub fn fold_range<R: RangeBounds<K>>(&self, range: R) -> S {
+        return self.map.fold_range(range);
+    }
+
+    /* As `RBMap::validate`, except that equal keys are allowed. */
+    pub fn validate(&self) -> Result<(), Violation> where S: PartialEq {
+        return self.map.check(false, S::eq);
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn is_rb_tree(&self) -> bool {
+        return self.map.check(false, |_, _| true).is_ok();
+    }
+
+    #[cfg(any(feature = "std", test))]
+    #[allow(dead_code)]
+    pub(crate) fn print(&self) {
+        self.map.print();
+    }
+}
+
+impl<K, S, C> IntoIterator for RBMultiSet<K, S, C> {
+    type Item = K;
+    type IntoIter = IntoKeys<K, (), S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.into_keys()
+    }
+}
+
+impl<'a, K, S, C> IntoIterator for &'a RBMultiSet<K, S, C> {
+    type Item = &'a K;
+    type IntoIter = Keys<'a, K, (), S, C>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.map.keys()
+    }
+}
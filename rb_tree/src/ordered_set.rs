@@ -0,0 +1,166 @@
+use alloc::collections::{btree_set, BTreeSet};
+use alloc::vec::Vec;
+use core::fmt::Debug;
+use core::ops::{Bound, RangeBounds};
+
+use crate::summary::{above_lower, below_upper};
+use crate::{Comparator, Keys, NaturalOrder, RangeKeys, RBTree, Summary};
+
+/* The operations every ordered set backend provides, so that call sites can
+ * be written once and run against `RBTree`, `AvlTree`, `Treap` or
+ * `BTreeSet`. A range whose start lies after its end is empty for every
+ * backend. */
+pub trait OrderedSet<K> {
+    type Iter<'a>: Iterator<Item = &'a K> where Self: 'a, K: 'a;
+    type Range<'a>: Iterator<Item = &'a K> where Self: 'a, K: 'a;
+
+    /* Returns: whether `key` was added. */
+    fn insert(&mut self, key: K) -> bool;
+
+    /* Returns: whether `key` was present. */
+    fn remove(&mut self, key: &K) -> bool;
+
+    fn contains(&self, key: &K) -> bool;
+
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        return self.len() == 0;
+    }
+
+    /* Returns: an iterator over the keys in ascending order. */
+    fn iter(&self) -> Self::Iter<'_>;
+
+    /* Returns: an iterator over the keys that fall in `range`, in ascending order. */
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Self::Range<'_>;
+}
+
+impl<K: Debug, S: Summary<K, ()>, C: Comparator<K>> OrderedSet<K> for RBTree<K, S, C> {
+    type Iter<'a> = Keys<'a, K, (), S, C> where Self: 'a, K: 'a;
+    type Range<'a> = RangeKeys<'a, K, (), S, C> where Self: 'a, K: 'a;
+
+    fn insert(&mut self, key: K) -> bool {
+        return RBTree::insert(self, key);
+    }
+
+    fn remove(&mut self, key: &K) -> bool {
+        return RBTree::remove(self, key);
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        return RBTree::contains(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return RBTree::len(self);
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        return RBTree::iter(self);
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Self::Range<'_> {
+        return RBTree::range(self, range);
+    }
+}
+
+impl<K: Ord> OrderedSet<K> for BTreeSet<K> {
+    type Iter<'a> = btree_set::Iter<'a, K> where Self: 'a, K: 'a;
+    type Range<'a> = btree_set::Range<'a, K> where Self: 'a, K: 'a;
+
+    fn insert(&mut self, key: K) -> bool {
+        return BTreeSet::insert(self, key);
+    }
+
+    fn remove(&mut self, key: &K) -> bool {
+        return BTreeSet::remove(self, key);
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        return BTreeSet::contains(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return BTreeSet::len(self);
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        return BTreeSet::iter(self);
+    }
+
+    /* `BTreeSet::range` panics on a range that ends before it starts, so
+     * such a range is swapped for an empty one that it accepts. */
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Self::Range<'_> {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        let inverted = match (start, end) {
+            (Bound::Included(s), Bound::Included(e)) => s > e,
+            (Bound::Included(s) | Bound::Excluded(s), Bound::Included(e) | Bound::Excluded(e)) => s >= e,
+            _ => false
+        };
+        if let (true, Bound::Included(s) | Bound::Excluded(s)) = (inverted, start) {
+            return BTreeSet::range(self, (Bound::Excluded(s), Bound::Included(s)));
+        }
+        return BTreeSet::range(self, (start, end));
+    }
+}
+
+/* A node of a tree of boxed nodes, as walked by `NodeIter`. */
+pub trait BinaryNode<K> {
+    fn key(&self) -> &K;
+    fn left(&self) -> Option<&Self>;
+    fn right(&self) -> Option<&Self>;
+}
+
+/* In-order iterator over a tree of boxed nodes. The stack holds the nodes
+ * still to be visited whose left subtrees are done; iteration stops after
+ * `last`, the last key in range. */
+pub struct NodeIter<'a, K, N> {
+    stack: Vec<&'a N>,
+    last: Option<&'a K>
+}
+
+impl<'a, K: PartialOrd, N: BinaryNode<K>> NodeIter<'a, K, N> {
+    /* Returns: an iterator over the keys under `root` that fall in `range`.
+     * Both ends are found with one descent each. */
+    pub(crate) fn new<R: RangeBounds<K>>(root: Option<&'a N>, range: R) -> Self {
+        let mut stack = Vec::new();
+        let mut x = root;
+        while let Some(node) = x {
+            if above_lower(&NaturalOrder, node.key(), range.start_bound()) {
+                stack.push(node);
+                x = node.left();
+            } else {
+                x = node.right();
+            }
+        }
+        let mut last = None;
+        let mut x = root;
+        while let Some(node) = x {
+            if below_upper(&NaturalOrder, node.key(), range.end_bound()) {
+                last = Some(node.key());
+                x = node.right();
+            } else {
+                x = node.left();
+            }
+        }
+        return NodeIter {stack, last};
+    }
+}
+
+impl<'a, K: PartialOrd, N: BinaryNode<K>> Iterator for NodeIter<'a, K, N> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        if self.last.is_none_or(|last| node.key() > last) {
+            self.stack.clear();
+            return None;
+        }
+        let mut x = node.right();
+        while let Some(child) = x {
+            self.stack.push(child);
+            x = child.left();
+        }
+        return Some(node.key());
+    }
+}
@@ -0,0 +1,449 @@
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use core::iter::FusedIterator;
+
+use crate::RBColor;
+
+/* An immutable ordered set. `insert` and `remove` return a new version and
+ * leave `self` untouched: only the nodes on the path to the change (and the
+ * siblings that get recolored or rotated) are copied, and every other subtree
+ * is shared between the two versions. Cloning a version is O(1), and since
+ * nodes are reference counted atomically, versions can be shared between
+ * threads (see `ConcurrentRBTree`).
+ *
+ * There are no parent links, since a shared node has many parents. The descent
+ * is recursive instead, and the cases of `insert_fixup` and `remove_fixup`
+ * run on the way back up, each frame playing the part of `p[z]` or `p[x]`. */
+pub struct PersistentRBTree<K: core::cmp::PartialOrd + Clone> {
+    root: Link<K>,
+    len: usize
+}
+
+type Link<K> = Option<Arc<PersistentNode<K>>>;
+
+#[derive(Clone)]
+struct PersistentNode<K> {
+    color: RBColor,
+    key: K,
+    left: Link<K>,
+    right: Link<K>
+}
+
+fn color<K>(x: &Link<K>) -> RBColor {
+    match x {
+        None => RBColor::Black,
+        Some(node) => node.color
+    }
+}
+
+/* Returns: the node behind `x`, copied first if another version shares it. */
+fn copy_on_write<K: Clone>(x: &mut Link<K>) -> &mut PersistentNode<K> {
+    return Arc::make_mut(x.as_mut().expect("INVALID STATE!"));
+}
+
+fn set_color<K: Clone>(x: &mut Link<K>, c: RBColor) {
+    if color(x) != c {
+        copy_on_write(x).color = c;
+    }
+}
+
+fn left_rotate<K: Clone>(x: &mut Link<K>) {
+    let mut x_node = x.take().expect("INVALID STATE!");
+    let x_mut = Arc::make_mut(&mut x_node);
+    let mut y_node = x_mut.right.take().expect("INVALID STATE!");
+    let y_mut = Arc::make_mut(&mut y_node);
+    x_mut.right = y_mut.left.take();
+    y_mut.left = Some(x_node);
+    *x = Some(y_node);
+}
+
+fn right_rotate<K: Clone>(x: &mut Link<K>) {
+    let mut x_node = x.take().expect("INVALID STATE!");
+    let x_mut = Arc::make_mut(&mut x_node);
+    let mut y_node = x_mut.left.take().expect("INVALID STATE!");
+    let y_mut = Arc::make_mut(&mut y_node);
+    x_mut.left = y_mut.right.take();
+    y_mut.right = Some(x_node);
+    *x = Some(y_node);
+}
+
+/* Inserts `key` below `x`. The child on the search path is only copied
+ * into `x` once the key turns out to be new. Returns: whether it was added;
+ * if not, `x` is untouched. */
+fn insert<K: core::cmp::PartialOrd + Clone>(x: &mut Link<K>, key: K) -> bool {
+    let Some(node) = x else {
+        *x = Some(Arc::new(PersistentNode {color: RBColor::Red, key, left: None, right: None}));
+        return true;
+    };
+    let goes_left = key < node.key;
+    if !goes_left && key == node.key {
+        return false;
+    }
+    let mut child = if goes_left {node.left.clone()} else {node.right.clone()};
+    if !insert(&mut child, key) {
+        return false;
+    }
+    let node = copy_on_write(x);
+    if goes_left {
+        node.left = child;
+    } else {
+        node.right = child;
+    }
+    insert_fixup(x);
+    return true;
+}
+
+/* One step of `insert_fixup` with `g` as p[p[z]]: if a child of `g` and one of
+ * its children are both red, recolor or rotate exactly as the iterative
+ * version does. A recolored `g` is red, and its own parent's frame checks it
+ * next. */
+fn insert_fixup<K: Clone>(g: &mut Link<K>) {
+    let g_node = copy_on_write(g);
+    if color(&g_node.left) == RBColor::Red {
+        let p = g_node.left.as_ref().expect("INVALID STATE!");
+        let z_is_right = color(&p.right) == RBColor::Red;
+        if z_is_right || color(&p.left) == RBColor::Red {
+            if color(&g_node.right) == RBColor::Red {
+                set_color(&mut g_node.left, RBColor::Black);
+                set_color(&mut g_node.right, RBColor::Black);
+                g_node.color = RBColor::Red;
+                return;
+            }
+            if z_is_right {
+                left_rotate(&mut g_node.left);
+            }
+            set_color(&mut g_node.left, RBColor::Black);
+            g_node.color = RBColor::Red;
+            right_rotate(g);
+            return;
+        }
+    }
+    if color(&g_node.right) == RBColor::Red {
+        let p = g_node.right.as_ref().expect("INVALID STATE!");
+        let z_is_left = color(&p.left) == RBColor::Red;
+        if z_is_left || color(&p.right) == RBColor::Red {
+            if color(&g_node.left) == RBColor::Red {
+                set_color(&mut g_node.left, RBColor::Black);
+                set_color(&mut g_node.right, RBColor::Black);
+                g_node.color = RBColor::Red;
+                return;
+            }
+            if z_is_left {
+                right_rotate(&mut g_node.right);
+            }
+            set_color(&mut g_node.right, RBColor::Black);
+            g_node.color = RBColor::Red;
+            left_rotate(g);
+        }
+    }
+}
+
+/* Removes `key` from below `x`, copying nodes only once it has been found.
+ * Returns: None if it is not there, leaving `x` untouched; otherwise whether
+ * the subtree at `x` is now one black node short on every path, which is the
+ * situation `remove_fixup` starts from. */
+fn remove<K: core::cmp::PartialOrd + Clone>(x: &mut Link<K>, key: &K) -> Option<bool> {
+    let node = x.as_ref()?;
+    let goes_left = key < &node.key;
+    if goes_left || key != &node.key {
+        let mut child = if goes_left {node.left.clone()} else {node.right.clone()};
+        let short = remove(&mut child, key)?;
+        let node = copy_on_write(x);
+        if goes_left {
+            node.left = child;
+        } else {
+            node.right = child;
+        }
+        return Some(short && remove_fixup(x, goes_left));
+    }
+    let node = copy_on_write(x);
+    if node.left.is_some() && node.right.is_some() {
+        /* As in `remove_node`, the successor takes the removed node's place;
+         * here that means moving its key up. */
+        let (successor, short) = remove_minimum(&mut node.right);
+        node.key = successor;
+        return Some(short && remove_fixup(x, false));
+    }
+    return Some(splice(x));
+}
+
+/* Removes the minimum below `x`. Returns: its key, and whether the subtree at
+ * `x` is now short. */
+fn remove_minimum<K: core::cmp::PartialOrd + Clone>(x: &mut Link<K>) -> (K, bool) {
+    let node = copy_on_write(x);
+    if node.left.is_some() {
+        let (key, short) = remove_minimum(&mut node.left);
+        return (key, short && remove_fixup(x, true));
+    }
+    let key = node.key.clone();
+    return (key, splice(x));
+}
+
+/* Replaces the node at `x`, which has at most one child, by that child. A
+ * red child absorbs the lost black; otherwise removing a black node leaves the
+ * subtree short. */
+fn splice<K: Clone>(x: &mut Link<K>) -> bool {
+    let node = copy_on_write(x);
+    let removed_color = node.color;
+    let child = if node.left.is_some() {node.left.take()} else {node.right.take()};
+    *x = child;
+    if removed_color == RBColor::Red {
+        return false;
+    }
+    if color(x) == RBColor::Red {
+        set_color(x, RBColor::Black);
+        return false;
+    }
+    return true;
+}
+
+/* The body of `remove_fixup` with `p` as p[x], where x is the left child of
+ * `p` if `x_is_left`. Returns: whether the subtree at `p` is still short, in
+ * which case the parent's frame carries on with x = p. */
+fn remove_fixup<K: Clone>(p: &mut Link<K>, x_is_left: bool) -> bool {
+    let p_node = copy_on_write(p);
+    let (x, w) = if x_is_left {(&mut p_node.left, &mut p_node.right)} else {(&mut p_node.right, &mut p_node.left)};
+    if color(x) == RBColor::Red {
+        set_color(x, RBColor::Black);
+        return false;
+    }
+    if color(w) == RBColor::Red {
+        /* Case 1: rotate the red sibling up; x keeps its parent, which is now
+         * red, so the cases below finish the job there. */
+        set_color(w, RBColor::Black);
+        p_node.color = RBColor::Red;
+        if x_is_left {
+            left_rotate(p);
+            let short = remove_fixup(&mut copy_on_write(p).left, true);
+            debug_assert!(!short);
+        } else {
+            right_rotate(p);
+            let short = remove_fixup(&mut copy_on_write(p).right, false);
+            debug_assert!(!short);
+        }
+        return false;
+    }
+    let w_node = copy_on_write(w);
+    let (near, far) = if x_is_left {(&mut w_node.left, &mut w_node.right)} else {(&mut w_node.right, &mut w_node.left)};
+    if color(near) == RBColor::Black && color(far) == RBColor::Black {
+        /* Case 2: the sibling gives up a black too, and the shortage moves up. */
+        w_node.color = RBColor::Red;
+        if p_node.color == RBColor::Red {
+            p_node.color = RBColor::Black;
+            return false;
+        }
+        return true;
+    }
+    if color(far) == RBColor::Black {
+        /* Case 3: turn it into case 4. */
+        set_color(near, RBColor::Black);
+        w_node.color = RBColor::Red;
+        if x_is_left {
+            right_rotate(w);
+        } else {
+            left_rotate(w);
+        }
+    }
+    /* Case 4 */
+    let w_node = copy_on_write(w);
+    w_node.color = p_node.color;
+    p_node.color = RBColor::Black;
+    set_color(if x_is_left {&mut w_node.right} else {&mut w_node.left}, RBColor::Black);
+    if x_is_left {
+        left_rotate(p);
+    } else {
+        right_rotate(p);
+    }
+    return false;
+}
+
+impl<K: core::cmp::PartialOrd + Clone> Clone for PersistentRBTree<K> {
+    fn clone(&self) -> Self {
+        PersistentRBTree {root: self.root.clone(), len: self.len}
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Clone> Default for PersistentRBTree<K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: core::cmp::PartialOrd + Clone> PersistentRBTree<K> {
+    pub fn new() -> PersistentRBTree<K> {
+        PersistentRBTree {root: None, len: 0}
+    }
+
+    /* Returns: a version that also holds `key`, or a clone of this one if it
+     * already does. Costs O(log n) time and new nodes. */
+    pub fn insert(&self, key: K) -> Self {
+        let mut root = self.root.clone();
+        if !insert(&mut root, key) {
+            return self.clone();
+        }
+        set_color(&mut root, RBColor::Black);
+        return PersistentRBTree {root, len: self.len + 1};
+    }
+
+    /* Returns: a version without `key`, or a clone of this one if it does not
+     * hold it. Costs O(log n) time and new nodes. */
+    pub fn remove(&self, key: &K) -> Self {
+        let mut root = self.root.clone();
+        if remove(&mut root, key).is_none() {
+            return self.clone();
+        }
+        set_color(&mut root, RBColor::Black);
+        return PersistentRBTree {root, len: self.len - 1};
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        let mut x = &self.root;
+        while let Some(node) = x {
+            if key < &node.key {
+                x = &node.left;
+            } else if key == &node.key {
+                return true;
+            } else {
+                x = &node.right;
+            }
+        }
+        return false;
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /* Returns: an iterator over the keys in ascending order. */
+    pub fn iter(&self) -> PersistentIter<'_, K> {
+        let mut iter = PersistentIter {stack: Vec::new(), remaining: self.len};
+        iter.push_left(&self.root);
+        return iter;
+    }
+
+    #[allow(dead_code)]
+    pub(crate) fn is_rb_tree(&self) -> bool {
+        fn check<K: core::cmp::PartialOrd>(x: &Link<K>, low: Option<&K>, high: Option<&K>) -> Option<usize> {
+            let Some(node) = x else {
+                return Some(0);
+            };
+            if low.is_some_and(|low| &node.key <= low) || high.is_some_and(|high| &node.key >= high) {
+                return None;
+            }
+            if node.color == RBColor::Red && (color(&node.left) == RBColor::Red || color(&node.right) == RBColor::Red) {
+                return None;
+            }
+            let left = check(&node.left, low, Some(&node.key))?;
+            let right = check(&node.right, Some(&node.key), high)?;
+            if left != right {
+                return None;
+            }
+            return Some(left + if node.color == RBColor::Black {1} else {0});
+        }
+        return color(&self.root) == RBColor::Black && check(&self.root, None, None).is_some();
+    }
+}
+
+/* In-order iterator over a `PersistentRBTree`. Without parent links it keeps
+ * the path of nodes whose left subtrees are being visited. */
+pub struct PersistentIter<'a, K> {
+    stack: Vec<&'a PersistentNode<K>>,
+    remaining: usize
+}
+
+impl<'a, K> PersistentIter<'a, K> {
+    fn push_left(&mut self, mut x: &'a Link<K>) {
+        while let Some(node) = x {
+            self.stack.push(node);
+            x = &node.left;
+        }
+    }
+}
+
+impl<'a, K> Iterator for PersistentIter<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let node = self.stack.pop()?;
+        self.remaining -= 1;
+        self.push_left(&node.right);
+        return Some(&node.key);
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<K> ExactSizeIterator for PersistentIter<'_, K> {}
+impl<K> FusedIterator for PersistentIter<'_, K> {}
+
+impl<'a, K: core::cmp::PartialOrd + Clone> IntoIterator for &'a PersistentRBTree<K> {
+    type Item = &'a K;
+    type IntoIter = PersistentIter<'a, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+#[cfg(test)]
+mod fuzzer {
+    use super::*;
+    use std::collections::{BTreeSet, HashSet};
+
+    /* Returns: the number of nodes of `x` that are not shared with `old`. */
+    fn fresh_nodes<K>(x: &Link<K>, old: &HashSet<*const PersistentNode<K>>) -> usize {
+        match x {
+            None => 0,
+            Some(node) if old.contains(&Arc::as_ptr(node)) => 0,
+            Some(node) => 1 + fresh_nodes(&node.left, old) + fresh_nodes(&node.right, old)
+        }
+    }
+
+    fn all_nodes<K>(x: &Link<K>, into: &mut HashSet<*const PersistentNode<K>>) {
+        if let Some(node) = x {
+            into.insert(Arc::as_ptr(node));
+            all_nodes(&node.left, into);
+            all_nodes(&node.right, into);
+        }
+    }
+
+    #[test]
+    fn test_persistent_versions() {
+        for _ in 0..20 {
+            let mut versions = vec![(PersistentRBTree::<u16>::new(), BTreeSet::<u16>::new())];
+            for _ in 0..1000 {
+                /* Branch off a random earlier version, not just the latest. */
+                let (tree, set) = &versions[rand::random::<usize>() % versions.len()];
+                let key = rand::random::<u16>() % 512;
+                let mut set = set.clone();
+                let (next, changed) = if rand::random::<u32>().is_multiple_of(3) {
+                    (tree.remove(&key), set.remove(&key))
+                } else {
+                    (tree.insert(key), set.insert(key))
+                };
+                assert!(next.is_rb_tree());
+                assert_eq!(next.len(), set.len());
+
+                let mut old = HashSet::new();
+                all_nodes(&tree.root, &mut old);
+                let height_bound = 2 * (usize::BITS - next.len().leading_zeros()) as usize + 2;
+                assert!(fresh_nodes(&next.root, &old) <= 2 * height_bound);
+                /* A no-op shares the whole tree instead of copying a path. */
+                assert!(changed || fresh_nodes(&next.root, &old) == 0);
+                versions.push((next, set));
+            }
+            for (tree, set) in &versions {
+                assert!(tree.is_rb_tree());
+                assert!(tree.iter().eq(set.iter()));
+                let probe = rand::random::<u16>() % 512;
+                assert_eq!(tree.contains(&probe), set.contains(&probe));
+            }
+        }
+    }
+}
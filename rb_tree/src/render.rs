@@ -0,0 +1,243 @@
+use alloc::format;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+use core::fmt::{self, Debug, Write};
+
+use crate::{NaturalOrder, NodeId, RBColor, RBMap, RBNode, RBTree, Summary};
+
+#[derive(Copy, Clone, PartialEq)]
+enum Format {
+    Text,
+    #[cfg_attr(not(any(feature = "std", test)), allow(dead_code))]
+    Ansi, /* `Text` with red nodes highlighted, for `print` */
+    Dot,
+    Svg
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Copy, Clone, PartialEq)]
+enum NodeType {ROOT, LEFT, RIGHT}
+
+/* Horizontal distance between neighbouring keys and vertical distance
+ * between levels in the SVG drawing. */
+const SVG_STEP: usize = 40;
+const SVG_LEVEL: usize = 60;
+const SVG_RADIUS: usize = 15;
+
+/* The shape of a tree, rendered as text, Graphviz DOT or SVG. It is written
+ * out through `Display`, so it can go to a `String` with `to_string`, to a
+ * `fmt::Write` or to an `io::Write` with `write!`. Keys are labelled with
+ * their `Debug` form. */
+pub struct Render<'a, K, V, S = (), C = NaturalOrder> {
+    map: &'a RBMap<K, V, S, C>,
+    format: Format,
+    sizes: bool,
+    summary: Option<Label<K, V, S, C>>
+}
+
+/* Writes out the summary of the subtree at a node. */
+type Label<K, V, S, C> = fn(&RBMap<K, V, S, C>, NodeId<K, V, S>) -> String;
+
+impl<'a, K, V, S, C> Render<'a, K, V, S, C> {
+    /* Labels every node with the number of keys in its subtree. */
+    pub fn with_sizes(mut self) -> Self {
+        self.sizes = true;
+        return self;
+    }
+}
+
+impl<'a, K, V, S: Summary<K, V> + Debug, C> Render<'a, K, V, S, C> {
+    /* Labels every node with the summary of its subtree. */
+    pub fn with_summaries(mut self) -> Self {
+        self.summary = Some(|map, x| format!("{:?}", map.summary_of(Some(x))));
+        return self;
+    }
+}
+
+impl<K, V, S, C> RBMap<K, V, S, C> {
+    fn render(&self, format: Format) -> Render<'_, K, V, S, C> {
+        return Render {map: self, format, sizes: false, summary: None};
+    }
+
+    /* Returns: the tree drawn sideways as plain text, root on the left and
+     * larger keys above smaller ones, with each node marked `R` or `B`. */
+    pub fn text(&self) -> Render<'_, K, V, S, C> {
+        return self.render(Format::Text);
+    }
+
+    /* Returns: the tree as a Graphviz `digraph`, with nodes filled in their
+     * colors. */
+    pub fn dot(&self) -> Render<'_, K, V, S, C> {
+        return self.render(Format::Dot);
+    }
+
+    /* Returns: the tree as a standalone SVG image: keys are spread out in
+     * order from left to right and each level sits below its parent's. */
+    pub fn svg(&self) -> Render<'_, K, V, S, C> {
+        return self.render(Format::Svg);
+    }
+
+    #[cfg(any(feature = "std", test))]
+    #[allow(dead_code)]
+    pub(crate) fn print(&self) where K: Debug {
+        print!("{}", self.render(Format::Ansi));
+    }
+}
+
+impl<K, S, C> RBTree<K, S, C> {
+    pub fn text(&self) -> Render<'_, K, (), S, C> {
+        return self.map.text();
+    }
+
+    pub fn dot(&self) -> Render<'_, K, (), S, C> {
+        return self.map.dot();
+    }
+
+    pub fn svg(&self) -> Render<'_, K, (), S, C> {
+        return self.map.svg();
+    }
+}
+
+impl<K: Debug, V, S, C> fmt::Display for Render<'_, K, V, S, C> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.format {
+            Format::Text | Format::Ansi => self.write_text(f, self.map.root, 0, NodeType::ROOT, &mut String::new()),
+            Format::Dot => self.write_dot(f),
+            Format::Svg => self.write_svg(f)
+        }
+    }
+}
+
+impl<K: Debug, V, S, C> Render<'_, K, V, S, C> {
+    /* Returns: the lines that annotate node `x`, if any were asked for. */
+    fn annotations(&self, x: NodeId<K, V, S>) -> Vec<String> {
+        let mut lines = Vec::new();
+        if self.sizes {
+            lines.push(format!("size={}", self.map.get_size(Some(x))));
+        }
+        if let Some(summary) = self.summary {
+            lines.push(summary(self.map, x));
+        }
+        return lines;
+    }
+
+    /* The sideways layout `print` has always used. `prefix` holds the
+     * connecting lines of the levels above. */
+    fn write_text<W: Write>(&self, out: &mut W, x: RBNode<K, V, S>, indent: u32, nt: NodeType, prefix: &mut String) -> fmt::Result {
+        let Some(id) = x else {
+            return Ok(());
+        };
+        let node = self.map.node(id);
+        let len = prefix.len();
+        prefix.push_str(if nt == NodeType::LEFT {"| "} else {"  "});
+        self.write_text(out, node.right, indent + 1, NodeType::RIGHT, prefix)?;
+        prefix.truncate(len);
+
+        write!(out, "{}", prefix)?;
+        if 0 < indent {
+            write!(out, "{}", if nt == NodeType::LEFT {"└"} else {"┌"})?;
+        }
+        match (self.format, node.color) {
+            (Format::Ansi, RBColor::Red) => write!(out, "\x1b[41m- {:?} \x1b[0m", node.key)?,
+            (Format::Ansi, RBColor::Black) => write!(out, "- {:?} \x1b[0m", node.key)?,
+            (_, RBColor::Red) => write!(out, "- {:?} R", node.key)?,
+            (_, RBColor::Black) => write!(out, "- {:?} B", node.key)?
+        }
+        for line in self.annotations(id) {
+            write!(out, " [{}]", line)?;
+        }
+        writeln!(out)?;
+
+        prefix.push_str(if nt == NodeType::RIGHT {"| "} else {"  "});
+        self.write_text(out, node.left, indent + 1, NodeType::LEFT, prefix)?;
+        prefix.truncate(len);
+        return Ok(());
+    }
+
+    fn write_dot<W: Write>(&self, out: &mut W) -> fmt::Result {
+        writeln!(out, "digraph RBTree {{")?;
+        writeln!(out, "    node [shape=circle, style=filled, fontcolor=white];")?;
+        let mut stack = self.map.root.into_iter().collect::<Vec<_>>();
+        while let Some(id) = stack.pop() {
+            let node = self.map.node(id);
+            let mut label = escape_dot(&format!("{:?}", node.key));
+            for line in self.annotations(id) {
+                label.push_str("\\n");
+                label.push_str(&escape_dot(&line));
+            }
+            let color = if node.color == RBColor::Red {"red"} else {"black"};
+            writeln!(out, "    n{} [label=\"{}\", fillcolor={}];", id.index(), label, color)?;
+            if node.left.is_none() && node.right.is_none() {
+                continue;
+            }
+            /* A missing child is drawn as a point so the other one stays on
+             * its own side. */
+            for (side, child) in [("l", node.left), ("r", node.right)] {
+                match child {
+                    Some(child) => {
+                        writeln!(out, "    n{} -> n{};", id.index(), child.index())?;
+                        stack.push(child);
+                    }
+                    None => {
+                        writeln!(out, "    nil{}{} [shape=point];", side, id.index())?;
+                        writeln!(out, "    n{} -> nil{}{};", id.index(), side, id.index())?;
+                    }
+                }
+            }
+        }
+        return writeln!(out, "}}");
+    }
+
+    /* Collects every node with its in-order position and depth. */
+    fn layout(&self, x: RBNode<K, V, S>, depth: usize, nodes: &mut Vec<(NodeId<K, V, S>, usize, usize)>) {
+        if let Some(id) = x {
+            self.layout(self.map.node(id).left, depth + 1, nodes);
+            nodes.push((id, nodes.len(), depth));
+            self.layout(self.map.node(id).right, depth + 1, nodes);
+        }
+    }
+
+    fn write_svg<W: Write>(&self, out: &mut W) -> fmt::Result {
+        let mut nodes = Vec::new();
+        self.layout(self.map.root, 0, &mut nodes);
+        let levels = nodes.iter().map(|&(_, _, depth)| depth + 1).max().unwrap_or(0);
+        let extra = self.sizes as usize + self.summary.is_some() as usize;
+        let width = (nodes.len() + 1) * SVG_STEP;
+        let height = levels * SVG_LEVEL + extra * 12 + SVG_RADIUS;
+        let position = |&(_, i, depth): &(NodeId<K, V, S>, usize, usize)| ((i + 1) * SVG_STEP, depth * SVG_LEVEL + SVG_LEVEL / 2);
+        let mut index = vec![None; self.map.nodes.len()];
+        for (i, entry) in nodes.iter().enumerate() {
+            index[entry.0.index()] = Some(i);
+        }
+
+        writeln!(out, "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\" font-family=\"monospace\" font-size=\"12\" text-anchor=\"middle\">", width, height, width, height)?;
+        /* Edges first, so the nodes are drawn over them. */
+        for entry in &nodes {
+            let (x1, y1) = position(entry);
+            for child in [self.map.node(entry.0).left, self.map.node(entry.0).right].into_iter().flatten() {
+                let (x2, y2) = position(&nodes[index[child.index()].expect("INVALID STATE!")]);
+                writeln!(out, "  <line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke=\"gray\"/>", x1, y1, x2, y2)?;
+            }
+        }
+        for entry in &nodes {
+            let (x, y) = position(entry);
+            let node = self.map.node(entry.0);
+            let color = if node.color == RBColor::Red {"red"} else {"black"};
+            writeln!(out, "  <circle cx=\"{}\" cy=\"{}\" r=\"{}\" fill=\"{}\"/>", x, y, SVG_RADIUS, color)?;
+            writeln!(out, "  <text x=\"{}\" y=\"{}\" fill=\"white\">{}</text>", x, y + 4, escape_xml(&format!("{:?}", node.key)))?;
+            for (i, line) in self.annotations(entry.0).iter().enumerate() {
+                writeln!(out, "  <text x=\"{}\" y=\"{}\" fill=\"gray\" font-size=\"9\">{}</text>", x, y + SVG_RADIUS + 10 + i * 12, escape_xml(line))?;
+            }
+        }
+        return writeln!(out, "</svg>");
+    }
+}
+
+fn escape_dot(label: &str) -> String {
+    return label.replace('\\', "\\\\").replace('"', "\\\"");
+}
+
+fn escape_xml(text: &str) -> String {
+    return text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;");
+}
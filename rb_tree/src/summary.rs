@@ -0,0 +1,103 @@
+use core::cmp::Ordering;
+use core::ops::{Bound, RangeBounds};
+
+use crate::{Comparator, RBMap};
+
+/* A per-subtree aggregate kept up to date on every node of an `RBMap` or
+ * `RBTree`. Summaries form a monoid: `combine` must be associative and `empty`
+ * must be its identity. They are combined in key order, so `combine` does not
+ * have to be commutative. */
+pub trait Summary<K, V>: Clone {
+    fn empty() -> Self;
+
+    /* Returns: the summary of a single entry. */
+    fn from_entry(key: &K, value: &V) -> Self;
+
+    /* Returns: the summary of the entries of `left` followed by those of `right`. */
+    fn combine(left: &Self, right: &Self) -> Self;
+}
+
+/* The summary of a tree that does not need one. */
+impl<K, V> Summary<K, V> for () {
+    fn empty() -> Self {}
+
+    fn from_entry(_: &K, _: &V) -> Self {}
+
+    fn combine(_: &Self, _: &Self) -> Self {}
+}
+
+pub(crate) fn above_lower<K, C: Comparator<K>>(cmp: &C, key: &K, bound: Bound<&K>) -> bool {
+    match bound {
+        Bound::Included(b) => cmp.compare(key, b) != Ordering::Less,
+        Bound::Excluded(b) => cmp.compare(key, b) == Ordering::Greater,
+        Bound::Unbounded => true
+    }
+}
+
+pub(crate) fn below_upper<K, C: Comparator<K>>(cmp: &C, key: &K, bound: Bound<&K>) -> bool {
+    match bound {
+        Bound::Included(b) => cmp.compare(key, b) != Ordering::Greater,
+        Bound::Excluded(b) => cmp.compare(key, b) == Ordering::Less,
+        Bound::Unbounded => true
+    }
+}
+
+impl<K, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Returns: the summary of every entry in the map. */
+    pub fn summary(&self) -> S {
+        return self.summary_of(self.root);
+    }
+
+    /* Returns: the summary of the entries whose keys fall in `range`. Descends
+     * to the first node inside the range, then walks one path down each side
+     * of it, picking up whole subtree summaries, so it costs O(log n). */
+    pub fn fold_range<R: RangeBounds<K>>(&self, range: R) -> S {
+        let (start, end) = (range.start_bound(), range.end_bound());
+        let mut split = self.root;
+        while let Some(id) = split {
+            let node = self.node(id);
+            if !above_lower(&self.cmp, &node.key, start) {
+                split = node.right;
+            } else if !below_upper(&self.cmp, &node.key, end) {
+                split = node.left;
+            } else {
+                break;
+            }
+        }
+        let Some(split) = split else {
+            return S::empty();
+        };
+
+        /* Keys in the left subtree are all below `end`; collect those above
+         * `start`, prepending as the walk moves to smaller keys. */
+        let mut lower = S::empty();
+        let mut x = self.node(split).left;
+        while let Some(id) = x {
+            let node = self.node(id);
+            if above_lower(&self.cmp, &node.key, start) {
+                let here = S::combine(&S::from_entry(&node.key, &node.value), &self.summary_of(node.right));
+                lower = S::combine(&here, &lower);
+                x = node.left;
+            } else {
+                x = node.right;
+            }
+        }
+
+        /* Symmetrically, keys in the right subtree are all above `start`. */
+        let mut upper = S::empty();
+        let mut x = self.node(split).right;
+        while let Some(id) = x {
+            let node = self.node(id);
+            if below_upper(&self.cmp, &node.key, end) {
+                let here = S::combine(&self.summary_of(node.left), &S::from_entry(&node.key, &node.value));
+                upper = S::combine(&upper, &here);
+                x = node.right;
+            } else {
+                x = node.left;
+            }
+        }
+
+        let node = self.node(split);
+        return S::combine(&S::combine(&lower, &S::from_entry(&node.key, &node.value)), &upper);
+    }
+}
@@ -0,0 +1,235 @@
+use alloc::boxed::Box;
+use core::cmp::Ordering;
+use core::ops::RangeBounds;
+
+use crate::ordered_set::{BinaryNode, NodeIter, OrderedSet};
+
+type Link<K> = Option<Box<TreapNode<K>>>;
+
+/* An ordered set balanced as a treap: a search tree on the keys that is also
+ * a max-heap on random priorities, which keeps its expected depth
+ * logarithmic. Priorities come from a generator seeded per treap, so runs
+ * are reproducible. */
+pub struct Treap<K> {
+    root: Link<K>,
+    len: usize,
+    state: u64 /* xorshift state for the next priority */
+}
+
+pub struct TreapNode<K> {
+    key: K,
+    priority: u64,
+    left: Link<K>,
+    right: Link<K>
+}
+
+impl<K> BinaryNode<K> for TreapNode<K> {
+    fn key(&self) -> &K {
+        return &self.key;
+    }
+
+    fn left(&self) -> Option<&Self> {
+        return self.left.as_deref();
+    }
+
+    fn right(&self) -> Option<&Self> {
+        return self.right.as_deref();
+    }
+}
+
+/* Returns: the keys under `x` below `key`, and those above it. `key` itself
+ * must not be present. */
+fn split<K: Ord>(x: Link<K>, key: &K) -> (Link<K>, Link<K>) {
+    let Some(mut node) = x else {
+        return (None, None);
+    };
+    if node.key < *key {
+        let (below, above) = split(node.right.take(), key);
+        node.right = below;
+        return (Some(node), above);
+    }
+    let (below, above) = split(node.left.take(), key);
+    node.left = above;
+    return (below, Some(node));
+}
+
+/* Returns: the union of `a` and `b`, where every key of `a` is below every
+ * key of `b`. */
+fn merge<K>(a: Link<K>, b: Link<K>) -> Link<K> {
+    match (a, b) {
+        (None, b) => b,
+        (a, None) => a,
+        (Some(mut a), Some(mut b)) => {
+            if a.priority > b.priority {
+                a.right = merge(a.right.take(), Some(b));
+                Some(a)
+            } else {
+                b.left = merge(Some(a), b.left.take());
+                Some(b)
+            }
+        }
+    }
+}
+
+/* Hangs `new` in place of the first node on its search path with a lower
+ * priority, splitting that node's subtree around it. An equal key met on the
+ * way down stops the insertion, as does one in the subtree about to be split;
+ * that subtree's search path is what the split walks, and its expected
+ * length is O(1). Returns: whether `new` was added. */
+fn insert<K: Ord>(link: &mut Link<K>, mut new: Box<TreapNode<K>>) -> bool {
+    match link {
+        Some(node) if node.priority >= new.priority => {
+            match new.key.cmp(&node.key) {
+                Ordering::Less => return insert(&mut node.left, new),
+                Ordering::Equal => return false,
+                Ordering::Greater => return insert(&mut node.right, new)
+            }
+        }
+        _ => {
+            if contains(link, &new.key) {
+                return false;
+            }
+            let (below, above) = split(link.take(), &new.key);
+            new.left = below;
+            new.right = above;
+            *link = Some(new);
+            return true;
+        }
+    }
+}
+
+/* Returns: whether `key` is in the subtree under `link`. */
+fn contains<K: Ord>(link: &Link<K>, key: &K) -> bool {
+    let mut x = link.as_deref();
+    while let Some(node) = x {
+        x = match key.cmp(&node.key) {
+            Ordering::Less => node.left.as_deref(),
+            Ordering::Equal => return true,
+            Ordering::Greater => node.right.as_deref()
+        };
+    }
+    return false;
+}
+
+/* Returns: whether `key` was removed from under `link`. */
+fn remove<K: Ord>(link: &mut Link<K>, key: &K) -> bool {
+    let Some(node) = link else {
+        return false;
+    };
+    match key.cmp(&node.key) {
+        Ordering::Less => return remove(&mut node.left, key),
+        Ordering::Greater => return remove(&mut node.right, key),
+        Ordering::Equal => {
+            let mut node = link.take().expect("INVALID STATE!");
+            *link = merge(node.left.take(), node.right.take());
+            return true;
+        }
+    }
+}
+
+impl<K> Default for Treap<K> {
+    fn default() -> Self {
+        Treap {root: None, len: 0, state: 0x9E37_79B9_7F4A_7C15}
+    }
+}
+
+impl<K: Ord> Treap<K> {
+    pub fn new() -> Treap<K> {
+        return Treap::default();
+    }
+
+    /* Returns: an empty treap whose priorities are drawn from `seed`. */
+    pub fn with_seed(seed: u64) -> Treap<K> {
+        /* xorshift never leaves the all-zero state. */
+        return Treap {root: None, len: 0, state: seed.max(1)};
+    }
+
+    fn next_priority(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        return self.state;
+    }
+
+    /* Returns: whether `key` was added. */
+    pub fn insert(&mut self, key: K) -> bool {
+        let priority = self.next_priority();
+        let added = insert(&mut self.root, Box::new(TreapNode {key, priority, left: None, right: None}));
+        self.len += added as usize;
+        return added;
+    }
+
+    /* Returns: whether `key` was present. */
+    pub fn remove(&mut self, key: &K) -> bool {
+        let removed = remove(&mut self.root, key);
+        self.len -= removed as usize;
+        return removed;
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        return contains(&self.root, key);
+    }
+
+    pub fn len(&self) -> usize {
+        return self.len;
+    }
+
+    pub fn is_empty(&self) -> bool {
+        return self.len == 0;
+    }
+
+    /* Returns: an iterator over the keys in ascending order. */
+    pub fn iter(&self) -> NodeIter<'_, K, TreapNode<K>> {
+        return self.range(..);
+    }
+
+    /* Returns: an iterator over the keys that fall in `range`, in ascending order. */
+    pub fn range<R: RangeBounds<K>>(&self, range: R) -> NodeIter<'_, K, TreapNode<K>> {
+        return NodeIter::new(self.root.as_deref(), range);
+    }
+
+    /* Checks the search order on keys and the heap order on priorities. */
+    #[allow(dead_code)]
+    pub(crate) fn is_treap(&self) -> bool {
+        fn check<K: Ord>(x: &Link<K>, low: Option<&K>, high: Option<&K>, max: u64) -> bool {
+            let Some(node) = x else {
+                return true;
+            };
+            return low.is_none_or(|low| node.key > *low)
+                && high.is_none_or(|high| node.key < *high)
+                && node.priority <= max
+                && check(&node.left, low, Some(&node.key), node.priority)
+                && check(&node.right, Some(&node.key), high, node.priority);
+        }
+        return check(&self.root, None, None, u64::MAX) && self.iter().count() == self.len;
+    }
+}
+
+impl<K: Ord> OrderedSet<K> for Treap<K> {
+    type Iter<'a> = NodeIter<'a, K, TreapNode<K>> where Self: 'a, K: 'a;
+    type Range<'a> = NodeIter<'a, K, TreapNode<K>> where Self: 'a, K: 'a;
+
+    fn insert(&mut self, key: K) -> bool {
+        return Treap::insert(self, key);
+    }
+
+    fn remove(&mut self, key: &K) -> bool {
+        return Treap::remove(self, key);
+    }
+
+    fn contains(&self, key: &K) -> bool {
+        return Treap::contains(self, key);
+    }
+
+    fn len(&self) -> usize {
+        return Treap::len(self);
+    }
+
+    fn iter(&self) -> Self::Iter<'_> {
+        return Treap::iter(self);
+    }
+
+    fn range<R: RangeBounds<K>>(&self, range: R) -> Self::Range<'_> {
+        return Treap::range(self, range);
+    }
+}
@@ -0,0 +1,201 @@
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt;
+
+use crate::{Comparator, NodeId, RBColor, RBMap, RBNode, RBNodeInternal, RBTree, Slot, Summary};
+
+/* The invariant a `Violation` broke. */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Invariant {
+    RedRoot,
+    RedRed, /* a red node has a red parent */
+    BlackHeight, /* the two children have different black heights */
+    Order, /* a key does not sort after the one before it */
+    Parent, /* a parent link does not match the child link pointing here */
+    Size, /* a stored subtree size is wrong */
+    Summary, /* a stored subtree summary is wrong */
+    Stale, /* a node marked stale has a parent that is not */
+    Length, /* the map's length does not match its nodes */
+    Arena /* a link leaves the arena, or a slot is neither in the tree nor on the free list */
+}
+
+/* A step from a node to one of its children. */
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Side {
+    Left,
+    Right
+}
+
+/* A broken invariant, and the path from the root to the node that breaks it.
+ * `Length` and a leaked or cyclic free list concern the whole map and come
+ * with an empty path. */
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Violation {
+    pub invariant: Invariant,
+    pub path: Vec<Side>
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let what = match self.invariant {
+            Invariant::RedRoot => "the root is red",
+            Invariant::RedRed => "red node has a red parent",
+            Invariant::BlackHeight => "children have different black heights",
+            Invariant::Order => "key is out of order",
+            Invariant::Parent => "parent link does not match",
+            Invariant::Size => "subtree size is wrong",
+            Invariant::Summary => "subtree summary is wrong",
+            Invariant::Stale => "stale node has a settled parent",
+            Invariant::Length => "length does not match the tree",
+            Invariant::Arena => "arena link or slot is invalid"
+        };
+        write!(f, "{} at root", what)?;
+        for side in &self.path {
+            write!(f, "{}", if *side == Side::Left {".left"} else {".right"})?;
+        }
+        return Ok(());
+    }
+}
+
+impl core::error::Error for Violation {}
+
+/* The state of one walk over the tree. */
+struct Check<'a, K, V, S, C, F> {
+    map: &'a RBMap<K, V, S, C>,
+    strict: bool, /* equal keys are an `Order` violation */
+    same_summary: F,
+    path: Vec<Side>,
+    prev: Option<NodeId<K, V, S>> /* the last node visited in order */
+}
+
+impl<'a, K, V, S: Summary<K, V>, C: Comparator<K>, F: Fn(&S, &S) -> bool> Check<'a, K, V, S, C, F> {
+    fn violation(&self, invariant: Invariant) -> Violation {
+        return Violation {invariant, path: self.path.clone()};
+    }
+
+    /* Follows a link without trusting it. Returns: the node, or an `Arena`
+     * violation if the link points past the arena or at a vacant slot. */
+    fn load(&self, x: NodeId<K, V, S>) -> Result<&'a RBNodeInternal<K, V, S>, Violation> {
+        return match self.map.nodes.get(x.index()) {
+            Some(Slot::Occupied(node)) => Ok(node),
+            _ => Err(self.violation(Invariant::Arena))
+        };
+    }
+
+    fn child(&mut self, side: Side, x: NodeId<K, V, S>, child: RBNode<K, V, S>) -> Result<usize, Violation> {
+        self.path.push(side);
+        if let Some(c) = child {
+            let node = self.load(c)?;
+            if node.p != Some(x) {
+                return Err(self.violation(Invariant::Parent));
+            }
+            if self.map.node(x).color == RBColor::Red && node.color == RBColor::Red {
+                return Err(self.violation(Invariant::RedRed));
+            }
+            if node.dirty && !self.map.node(x).dirty {
+                return Err(self.violation(Invariant::Stale));
+            }
+        }
+        let height = self.node(child)?;
+        self.path.pop();
+        return Ok(height);
+    }
+
+    /* Checks the subtree at `x` in order. Returns: its black height. */
+    fn node(&mut self, x: RBNode<K, V, S>) -> Result<usize, Violation> {
+        let Some(id) = x else {
+            return Ok(0);
+        };
+        let node = self.load(id)?;
+        let left = self.child(Side::Left, id, node.left)?;
+
+        if let Some(prev) = self.prev {
+            match self.map.compare(&self.map.node(prev).key, &node.key) {
+                Ordering::Less => {}
+                Ordering::Equal if !self.strict => {}
+                _ => return Err(self.violation(Invariant::Order))
+            }
+        }
+        self.prev = x;
+
+        let right = self.child(Side::Right, id, node.right)?;
+        if left != right {
+            return Err(self.violation(Invariant::BlackHeight));
+        }
+        let height = left + (node.color == RBColor::Black) as usize;
+        /* A stale size and summary are recomputed when the node is settled,
+         * and readers work them out from the children until then. */
+        if node.dirty {
+            return Ok(height);
+        }
+        if node.size != 1 + self.map.get_size(node.left) + self.map.get_size(node.right) {
+            return Err(self.violation(Invariant::Size));
+        }
+        let mut summary = S::from_entry(&node.key, &node.value);
+        if let Some(l) = node.left {
+            summary = S::combine(&self.map.node(l).summary, &summary);
+        }
+        if let Some(r) = node.right {
+            summary = S::combine(&summary, &self.map.node(r).summary);
+        }
+        if !(self.same_summary)(&node.summary, &summary) {
+            return Err(self.violation(Invariant::Summary));
+        }
+        return Ok(height);
+    }
+}
+
+impl<K, V, S: Summary<K, V>, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Checks every invariant in O(n). `strict` rejects equal keys, and
+     * `same_summary` decides whether a stored summary matches the recomputed
+     * one. */
+    pub(crate) fn check<F: Fn(&S, &S) -> bool>(&self, strict: bool, same_summary: F) -> Result<(), Violation> {
+        let mut check = Check {map: self, strict, same_summary, path: Vec::new(), prev: None};
+        if let Some(root) = self.root {
+            let root = check.load(root)?;
+            if root.p.is_some() {
+                return Err(check.violation(Invariant::Parent));
+            }
+            if root.color == RBColor::Red {
+                return Err(check.violation(Invariant::RedRoot));
+            }
+        }
+        check.node(self.root)?;
+        if self.get_size(self.root) != self.len {
+            return Err(Violation {invariant: Invariant::Length, path: Vec::new()});
+        }
+
+        /* Every slot is either holding a node of the tree or on the free list.
+         * A list longer than the arena must loop back on itself. */
+        let mut vacant = 0;
+        let mut x = self.free;
+        while let Some(id) = x {
+            if vacant == self.nodes.len() {
+                return Err(Violation {invariant: Invariant::Arena, path: Vec::new()});
+            }
+            match self.nodes.get(id.index()) {
+                Some(Slot::Vacant(next)) => x = *next,
+                _ => return Err(Violation {invariant: Invariant::Arena, path: Vec::new()})
+            }
+            vacant += 1;
+        }
+        if vacant + self.len != self.nodes.len() {
+            return Err(Violation {invariant: Invariant::Arena, path: Vec::new()});
+        }
+        return Ok(());
+    }
+}
+
+impl<K, V, S: Summary<K, V> + PartialEq, C: Comparator<K>> RBMap<K, V, S, C> {
+    /* Checks the red-black, ordering, link and augmentation invariants in
+     * O(n). Returns: the first violation found, which a correct map never has. */
+    pub fn validate(&self) -> Result<(), Violation> {
+        return self.check(true, S::eq);
+    }
+}
+
+impl<K, S: Summary<K, ()> + PartialEq, C: Comparator<K>> RBTree<K, S, C> {
+    pub fn validate(&self) -> Result<(), Violation> {
+        return self.map.validate();
+    }
+}
@@ -0,0 +1,336 @@
+#![allow(clippy::needless_return)]
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rb_tree::RBTree;
+use std::collections::BTreeSet;
+use std::ops::Bound::{Excluded, Unbounded};
+use std::panic::{self, AssertUnwindSafe};
+
+/* Differential testing: random operation sequences are applied to an `RBTree`
+ * and to a `BTreeSet`, and every answer and the tree's invariants are checked
+ * after each step. Runs are driven by a seed, so a failure can be rerun with
+ * `RB_TREE_SEED=<seed> cargo test --test differential`, and a failing sequence
+ * is shrunk to a short `replay(&[...])` call to paste into a regression test. */
+#[cfg(test)]
+mod differential {
+    use super::*;
+    use Op::*;
+
+    #[derive(Clone, Copy, PartialEq, Debug)]
+    enum Op {
+        Insert(u16),
+        Remove(u16), /* the key may be missing */
+        Take(u16),
+        Replace(u16),
+        RemoveRange(u16, u16),
+        Retain(u16), /* keeps the keys that are not multiples of the argument + 2 */
+        Extract(u16), /* takes the first three multiples of the argument + 2 */
+        Contains(u16),
+        Floor(u16),
+        Ceiling(u16),
+        Lower(u16),
+        Higher(u16),
+        Min,
+        Max,
+        PopMin,
+        PopMax,
+        Range(u16, u16),
+        Order /* compares every key in order, both ways */
+    }
+
+    const OPS_PER_RUN: usize = 1000;
+
+    fn random_ops(seed: u64) -> Vec<Op> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        /* Small key spaces make hits and duplicate inserts common, large
+         * ones make misses common. */
+        let keys = [8, 64, 512, 4096][rng.gen_range(0..4)];
+        return (0..OPS_PER_RUN).map(|_| match rng.gen_range(0..26) {
+            24 => RemoveRange(rng.gen_range(0..keys), rng.gen_range(0..keys)),
+            25 => [Retain(rng.gen_range(0..8)), Extract(rng.gen_range(0..8))][rng.gen_range(0..2)],
+            0..=6 => Insert(rng.gen_range(0..keys)),
+            7..=9 => Remove(rng.gen_range(0..keys)),
+            10 => Take(rng.gen_range(0..keys)),
+            11 => Replace(rng.gen_range(0..keys)),
+            12..=13 => Contains(rng.gen_range(0..keys)),
+            14 => Floor(rng.gen_range(0..keys)),
+            15 => Ceiling(rng.gen_range(0..keys)),
+            16 => Lower(rng.gen_range(0..keys)),
+            17 => Higher(rng.gen_range(0..keys)),
+            18 => Min,
+            19 => Max,
+            20 => [PopMin, PopMax][rng.gen_range(0..2)],
+            21..=22 => Range(rng.gen_range(0..keys), rng.gen_range(0..keys)),
+            _ => Order
+        }).collect();
+    }
+
+    /* Applies one operation to both sets. Returns: a description of the first
+     * difference, if any. */
+    fn step(tree: &mut RBTree<u16>, model: &mut BTreeSet<u16>, op: Op) -> Result<(), String> {
+        let k = match op {
+            Insert(k) => {
+                compare("insert", tree.insert(k), model.insert(k))?;
+                k
+            }
+            Remove(k) => {
+                compare("remove", tree.remove(&k), model.remove(&k))?;
+                k
+            }
+            Take(k) => {
+                compare("take", tree.take(&k), model.take(&k))?;
+                k
+            }
+            Replace(k) => {
+                compare("replace", tree.replace(k), model.replace(k))?;
+                k
+            }
+            RemoveRange(a, b) => {
+                let (a, b) = (a.min(b), a.max(b));
+                compare("remove_range", tree.remove_range(a..=b), model.range(a..=b).count())?;
+                model.retain(|k| !(a..=b).contains(k));
+                return check_all(tree, model);
+            }
+            Retain(m) => {
+                tree.retain(|k| k % (m + 2) != 0);
+                model.retain(|k| k % (m + 2) != 0);
+                return check_all(tree, model);
+            }
+            Extract(m) => {
+                let want = model.iter().filter(|&k| k % (m + 2) == 0).take(3).copied().collect::<Vec<_>>();
+                compare("extract_if", tree.extract_if(|k| k % (m + 2) == 0).take(3).collect(), want.clone())?;
+                model.retain(|k| !want.contains(k));
+                return check_all(tree, model);
+            }
+            Contains(k) => return compare("contains", tree.contains(&k), model.contains(&k)),
+            Floor(k) => return compare("floor", tree.floor(&k), model.range(..=k).next_back()),
+            Ceiling(k) => return compare("ceiling", tree.ceiling(&k), model.range(k..).next()),
+            Lower(k) => return compare("lower", tree.lower(&k), model.range(..k).next_back()),
+            Higher(k) => return compare("higher", tree.higher(&k), model.range((Excluded(k), Unbounded)).next()),
+            Min => {
+                compare("min", tree.iter().next(), model.first())?;
+                return compare("first", tree.first(), model.first());
+            }
+            Max => {
+                compare("max", tree.iter().next_back(), model.last())?;
+                return compare("last", tree.last(), model.last());
+            }
+            PopMin => {
+                compare("pop_first", tree.pop_first(), model.pop_first())?;
+                return check_all(tree, model);
+            }
+            PopMax => {
+                compare("pop_last", tree.pop_last(), model.pop_last())?;
+                return check_all(tree, model);
+            }
+            Range(a, b) => {
+                let (a, b) = (a.min(b), a.max(b));
+                return compare("range", tree.range(a..b).collect::<Vec<_>>(), model.range(a..b).collect());
+            }
+            Order => {
+                compare("order", tree.iter().collect::<Vec<_>>(), model.iter().collect())?;
+                return compare("reverse order", tree.iter().rev().collect::<Vec<_>>(), model.iter().rev().collect());
+            }
+        };
+        compare("contains", tree.contains(&k), model.contains(&k))?;
+        compare("len", tree.len(), model.len())?;
+        return tree.validate().map_err(|violation| violation.to_string());
+    }
+
+    /* Checks the whole contents after an operation that may change many keys. */
+    fn check_all(tree: &RBTree<u16>, model: &BTreeSet<u16>) -> Result<(), String> {
+        compare("keys", tree.iter().collect::<Vec<_>>(), model.iter().collect())?;
+        compare("len", tree.len(), model.len())?;
+        return tree.validate().map_err(|violation| violation.to_string());
+    }
+
+    fn compare<T: PartialEq + std::fmt::Debug>(what: &str, got: T, want: T) -> Result<(), String> {
+        if got == want {
+            return Ok(());
+        }
+        return Err(format!("{}: got {:?}, expected {:?}", what, got, want));
+    }
+
+    /* Runs `ops` from empty sets. A panic inside the tree counts as a failure.
+     * Returns: the index of the failing operation and what went wrong. */
+    fn run(ops: &[Op]) -> Result<(), (usize, String)> {
+        let mut tree = RBTree::new();
+        let mut model = BTreeSet::new();
+        for (i, &op) in ops.iter().enumerate() {
+            match panic::catch_unwind(AssertUnwindSafe(|| step(&mut tree, &mut model, op))) {
+                Ok(Ok(())) => {}
+                Ok(Err(message)) => return Err((i, message)),
+                Err(_) => return Err((i, String::from("panicked")))
+            }
+        }
+        return Ok(());
+    }
+
+    /* Returns: a smaller sequence that still fails `fails`. Drops everything
+     * after the failure, then removes ever smaller chunks of operations and
+     * moves keys towards 0, until no single change keeps it failing. */
+    fn shrink(ops: &[Op], fails: impl Fn(&[Op]) -> Option<usize>) -> Vec<Op> {
+        let mut ops = ops[..=fails(ops).expect("shrink: the sequence must fail")].to_vec();
+        loop {
+            let mut changed = false;
+            let mut chunk = ops.len() / 2;
+            while 0 < chunk {
+                let mut start = 0;
+                while start + chunk <= ops.len() {
+                    let mut candidate = ops.clone();
+                    candidate.drain(start..start + chunk);
+                    if let Some(i) = fails(&candidate) {
+                        candidate.truncate(i + 1);
+                        ops = candidate;
+                        changed = true;
+                    } else {
+                        start += chunk;
+                    }
+                }
+                chunk /= 2;
+            }
+            /* A key is usually only interesting together with its other
+             * uses, so try renaming it everywhere at once first. */
+            let mut keys = ops.iter().flat_map(|&op| keys_of(op)).collect::<Vec<_>>();
+            keys.sort();
+            keys.dedup();
+            for k in keys {
+                for smaller in smaller_keys(k) {
+                    let candidate = ops.iter().map(|&op| rename(op, k, smaller)).collect::<Vec<_>>();
+                    if fails(&candidate).is_some() {
+                        ops = candidate;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+            for i in 0..ops.len() {
+                for smaller in smaller_ops(ops[i]) {
+                    let mut candidate = ops.clone();
+                    candidate[i] = smaller;
+                    if fails(&candidate).is_some() {
+                        ops = candidate;
+                        changed = true;
+                        break;
+                    }
+                }
+            }
+            if !changed {
+                return ops;
+            }
+        }
+    }
+
+    fn smaller_keys(k: u16) -> Vec<u16> {
+        let mut keys = vec![0, k / 2, k - k.min(1)];
+        keys.dedup();
+        return keys.into_iter().filter(|&s| s < k).collect();
+    }
+
+    fn keys_of(op: Op) -> Vec<u16> {
+        return match op {
+            Insert(k) | Remove(k) | Take(k) | Replace(k) | Contains(k) | Floor(k) | Ceiling(k) | Lower(k) | Higher(k) => vec![k],
+            Range(a, b) | RemoveRange(a, b) => vec![a, b],
+            Min | Max | PopMin | PopMax | Retain(_) | Extract(_) | Order => Vec::new()
+        };
+    }
+
+    fn rename(op: Op, from: u16, to: u16) -> Op {
+        let key = |k| if k == from {to} else {k};
+        return match op {
+            Insert(k) => Insert(key(k)),
+            Remove(k) => Remove(key(k)),
+            Take(k) => Take(key(k)),
+            Replace(k) => Replace(key(k)),
+            Contains(k) => Contains(key(k)),
+            Floor(k) => Floor(key(k)),
+            Ceiling(k) => Ceiling(key(k)),
+            Lower(k) => Lower(key(k)),
+            Higher(k) => Higher(key(k)),
+            Range(a, b) => Range(key(a), key(b)),
+            RemoveRange(a, b) => RemoveRange(key(a), key(b)),
+            Min | Max | PopMin | PopMax | Retain(_) | Extract(_) | Order => op
+        };
+    }
+
+    /* Returns: simpler versions of `op`, simplest first. */
+    fn smaller_ops(op: Op) -> Vec<Op> {
+        return match op {
+            Insert(k) => smaller_keys(k).into_iter().map(Insert).collect(),
+            Remove(k) => smaller_keys(k).into_iter().map(Remove).collect(),
+            Take(k) => smaller_keys(k).into_iter().map(Take).collect(),
+            Replace(k) => smaller_keys(k).into_iter().map(Replace).collect(),
+            Contains(k) => smaller_keys(k).into_iter().map(Contains).collect(),
+            Floor(k) => smaller_keys(k).into_iter().map(Floor).collect(),
+            Ceiling(k) => smaller_keys(k).into_iter().map(Ceiling).collect(),
+            Lower(k) => smaller_keys(k).into_iter().map(Lower).collect(),
+            Higher(k) => smaller_keys(k).into_iter().map(Higher).collect(),
+            Range(a, b) => smaller_keys(a).into_iter().map(|a| Range(a, b)).chain(smaller_keys(b).into_iter().map(|b| Range(a, b))).collect(),
+            RemoveRange(a, b) => smaller_keys(a).into_iter().map(|a| RemoveRange(a, b)).chain(smaller_keys(b).into_iter().map(|b| RemoveRange(a, b))).collect(),
+            Retain(m) => smaller_keys(m).into_iter().map(Retain).collect(),
+            Extract(m) => smaller_keys(m).into_iter().map(Extract).collect(),
+            Min | Max | PopMin | PopMax | Order => Vec::new()
+        };
+    }
+
+    fn script(ops: &[Op]) -> String {
+        return format!("replay(&[{}]);", ops.iter().map(|op| format!("{:?}", op)).collect::<Vec<_>>().join(", "));
+    }
+
+    /* Runs one seed, and on failure panics with the shrunk replay script. */
+    fn check_seed(seed: u64) {
+        let ops = random_ops(seed);
+        if let Err((i, message)) = run(&ops) {
+            let minimal = shrink(&ops, |ops| run(ops).err().map(|(i, _)| i));
+            panic!("seed {} failed at operation {} ({}); minimal replay:\n    {}", seed, i, message, script(&minimal));
+        }
+    }
+
+    /* Runs a shrunk sequence, as pasted from a failure. */
+    fn replay(ops: &[Op]) {
+        if let Err((i, message)) = run(ops) {
+            panic!("replay failed at operation {} ({:?}): {}", i, ops[i], message);
+        }
+    }
+
+    #[test]
+    fn test_differential() {
+        if let Ok(seed) = std::env::var("RB_TREE_SEED") {
+            check_seed(seed.parse().expect("RB_TREE_SEED must be a number"));
+            return;
+        }
+        for seed in 0..200 {
+            check_seed(seed);
+        }
+        /* One fresh seed per run finds new sequences; the panic message
+         * names it. */
+        check_seed(rand::random());
+    }
+
+    #[test]
+    fn test_regressions() {
+        replay(&[Insert(1), Remove(1), Remove(1), Min, Max, Order]);
+        replay(&[Insert(3), Insert(1), Insert(2), Remove(3), Range(0, 3), Order]);
+    }
+
+    #[test]
+    fn test_shrink() {
+        /* A made-up bug: removing a key at or above 50 that was inserted
+         * earlier fails. Shrinking must find the two operations behind it. */
+        let fails = |ops: &[Op]| {
+            let mut seen = BTreeSet::new();
+            for (i, op) in ops.iter().enumerate() {
+                match *op {
+                    Insert(k) => {seen.insert(k);}
+                    Remove(k) if 50 <= k && seen.contains(&k) => return Some(i),
+                    _ => {}
+                }
+            }
+            return None;
+        };
+        let ops = (0..OPS_PER_RUN as u64).map(random_ops).find(|ops| fails(ops).is_some()).expect("no seed hits the bug");
+        assert_eq!(shrink(&ops, fails), vec![Insert(50), Remove(50)]);
+        assert_eq!(script(&[Insert(50), Remove(50)]), "replay(&[Insert(50), Remove(50)]);");
+    }
+}
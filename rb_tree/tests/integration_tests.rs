@@ -1,5 +1,8 @@
-use rb_tree::RBTree;
-use rand;
+use rb_tree::{AvlTree, Entry, IntervalTree, OrderedSet, PersistentRBTree, RBMap, RBMultiSet, RBTree, Summary, Treap};
+use std::collections::BTreeSet;
+use std::fmt::Write as _;
+use std::io::Write as _;
+use std::ops::Bound;
 
 #[cfg(test)]
 mod integration_tests {
@@ -12,4 +15,423 @@ mod integration_tests {
             tree.insert(i);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_map_get_and_remove() {
+        let mut map = RBMap::<i32, String>::new();
+        for i in 0..100 {
+            assert_eq!(map.insert(i, i.to_string()), None);
+        }
+        assert_eq!(map.insert(7, String::from("seven")), Some(String::from("7")));
+        map.get_mut(&8).unwrap().push('!');
+        assert_eq!(map.get(&7).map(String::as_str), Some("seven"));
+        assert_eq!(map.get(&8).map(String::as_str), Some("8!"));
+        assert_eq!(map.remove(&7), Some(String::from("seven")));
+        assert_eq!(map.remove(&7), None);
+        assert!(!map.contains_key(&7));
+        assert!(map.contains_key(&99));
+    }
+
+    #[test]
+    fn test_set_iterators() {
+        let mut tree = RBTree::<i32>::new();
+        for i in [5, 3, 8, 1, 4, 7, 9, 2, 6] {
+            tree.insert(i);
+        }
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.iter().copied().collect::<Vec<_>>(), (1..10).collect::<Vec<_>>());
+        assert_eq!((&tree).into_iter().rev().copied().collect::<Vec<_>>(), (1..10).rev().collect::<Vec<_>>());
+        assert_eq!(tree.into_iter().collect::<Vec<_>>(), (1..10).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_set_range() {
+        let mut tree = RBTree::<i32>::new();
+        for i in 0..50 {
+            tree.insert(i * 2);
+        }
+        assert_eq!(tree.range(10..20).copied().collect::<Vec<_>>(), vec![10, 12, 14, 16, 18]);
+        assert_eq!(tree.range(11..=20).copied().collect::<Vec<_>>(), vec![12, 14, 16, 18, 20]);
+        assert_eq!(tree.range(..5).rev().copied().collect::<Vec<_>>(), vec![4, 2, 0]);
+        assert_eq!(tree.range(95..).copied().collect::<Vec<_>>(), vec![96, 98]);
+        assert_eq!(tree.range(31..32).next(), None);
+    }
+
+    #[test]
+    fn test_map_entry_grouping() {
+        let mut groups = RBMap::<i32, Vec<i32>>::new();
+        for i in 0..20 {
+            groups.entry(i % 3).or_default().push(i);
+        }
+        groups.entry(3).or_insert_with(|| vec![-1]).push(-2);
+        assert_eq!(groups.remove(&3), Some(vec![-1, -2]));
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups.get(&1), Some(&vec![1, 4, 7, 10, 13, 16, 19]));
+        match groups.entry(5) {
+            Entry::Vacant(entry) => assert_eq!(entry.key(), &5),
+            Entry::Occupied(_) => panic!("5 was never inserted")
+        }
+        if let Entry::Occupied(mut entry) = groups.entry(2) {
+            assert_eq!(entry.insert(vec![]).len(), 6);
+        }
+        assert_eq!(groups.get(&2), Some(&vec![]));
+    }
+
+    #[test]
+    fn test_select_and_rank() {
+        let mut tree = RBTree::<i32>::new();
+        for i in (0..100).rev() {
+            tree.insert(i * 10);
+        }
+        assert_eq!(tree.select(0), Some(&0));
+        assert_eq!(tree.select(42), Some(&420));
+        assert_eq!(tree.select(100), None);
+        assert_eq!(tree.rank(&420), 42);
+        assert_eq!(tree.rank(&425), 43);
+        assert_eq!(tree.count_range(100..200), 10);
+        tree.remove(&150);
+        assert_eq!(tree.count_range(100..=200), 10);
+        assert_eq!(tree.select(15), Some(&160));
+    }
+
+    #[test]
+    fn test_set_algebra() {
+        let evens = (0..40).step_by(2).fold(RBTree::<i32>::new(), |mut t, i| {t.insert(i); t});
+        let threes = (0..40).step_by(3).fold(RBTree::<i32>::new(), |mut t, i| {t.insert(i); t});
+        let (low, high) = evens.split_at(&20);
+        assert_eq!(low.iter().copied().collect::<Vec<_>>(), vec![0, 2, 4, 6, 8, 10, 12, 14, 16, 18]);
+        assert_eq!(high.len(), 10);
+        let evens = low.join(high);
+        let sixes = evens.intersection(threes);
+        assert_eq!(sixes.iter().copied().collect::<Vec<_>>(), vec![0, 6, 12, 18, 24, 30, 36]);
+        let odds: RBTree<i32> = [1, 3, 6].into_iter().collect();
+        let mixed = sixes.symmetric_difference(odds);
+        assert_eq!(mixed.iter().copied().collect::<Vec<_>>(), vec![0, 1, 3, 12, 18, 24, 30, 36]);
+    }
+
+    #[test]
+    fn test_persistent_snapshots() {
+        let base = (0..10).fold(PersistentRBTree::<i32>::new(), |tree, i| tree.insert(i));
+        let with_extra = base.insert(42);
+        let without_five = base.remove(&5);
+        assert_eq!(base.iter().copied().collect::<Vec<_>>(), (0..10).collect::<Vec<_>>());
+        assert_eq!(with_extra.len(), 11);
+        assert!(with_extra.contains(&42) && !base.contains(&42));
+        assert!(!without_five.contains(&5) && base.contains(&5) && with_extra.contains(&5));
+        assert_eq!(without_five.remove(&5).len(), 9);
+    }
+
+    #[derive(Clone, PartialEq)]
+    struct Sum(i64);
+
+    impl Summary<i64, ()> for Sum {
+        fn empty() -> Self {
+            Sum(0)
+        }
+
+        fn from_entry(key: &i64, _: &()) -> Self {
+            Sum(*key)
+        }
+
+        fn combine(left: &Self, right: &Self) -> Self {
+            Sum(left.0 + right.0)
+        }
+    }
+
+    #[test]
+    fn test_set_fold_range() {
+        let mut tree = RBTree::<i64, Sum>::new_augmented();
+        for i in 1..=100 {
+            tree.insert(i);
+        }
+        assert_eq!(tree.summary().0, 5050);
+        assert_eq!(tree.fold_range(1..=10).0, 55);
+        tree.remove(&5);
+        assert_eq!(tree.fold_range(1..=10).0, 50);
+        assert_eq!(tree.fold_range(200..).0, 0);
+    }
+
+    #[test]
+    fn test_interval_overlaps() {
+        /* (source, length) spans as in the almanac maps */
+        let mut spans = IntervalTree::<u64, u64>::new();
+        for (source, dest, length) in [(98, 50, 2), (50, 52, 48), (0, 39, 15), (15, 0, 37)] {
+            spans.insert(source..source + length, dest);
+        }
+        let mut hits = spans.overlapping(40..60).map(|(iv, dest)| (iv.start, *dest)).collect::<Vec<_>>();
+        hits.sort();
+        assert_eq!(hits, vec![(15, 0), (50, 52)]);
+        assert_eq!(spans.containing(&99).map(|(iv, _)| iv.start).collect::<Vec<_>>(), vec![98]);
+        assert_eq!(spans.containing(&100).count(), 0);
+        assert_eq!(spans.remove(&(50..98)), Some(52));
+        assert_eq!(spans.overlapping(40..60).count(), 1);
+    }
+
+    /* Ordered by `source` alone, and not `PartialOrd` at all. */
+    #[derive(Debug)]
+    struct Span {
+        source: u64,
+        length: u64
+    }
+
+    #[test]
+    fn test_comparators() {
+        let mut spans = RBTree::with_comparator(|a: &Span, b: &Span| a.source.cmp(&b.source));
+        for (source, length) in [(98, 2), (50, 48), (0, 15), (15, 37)] {
+            spans.insert(Span {source, length});
+        }
+        assert!(spans.contains(&Span {source: 50, length: 0}));
+        assert_eq!(spans.iter().map(|span| span.source).collect::<Vec<_>>(), vec![0, 15, 50, 98]);
+        assert_eq!(spans.rank(&Span {source: 60, length: 0}), 3);
+        spans.remove(&Span {source: 15, length: 0});
+        assert_eq!(spans.iter().map(|span| span.length).collect::<Vec<_>>(), vec![15, 48, 2]);
+
+        let case_insensitive = |a: &String, b: &String| a.to_lowercase().cmp(&b.to_lowercase());
+        let mut words = RBMap::with_comparator(case_insensitive);
+        assert_eq!(words.insert("Apple".to_string(), 1), None);
+        assert_eq!(words.insert("APPLE".to_string(), 2), Some(1));
+        words.insert("banana".to_string(), 3);
+        assert_eq!(words.get(&"apple".to_string()), Some(&2));
+        assert_eq!(words.keys().collect::<Vec<_>>(), vec!["Apple", "banana"]);
+
+        let descending = RBTree::with_comparator(|a: &i32, b: &i32| b.cmp(a));
+        let descending = (0..5).fold(descending, |mut tree, i| {tree.insert(i); tree});
+        assert_eq!(descending.iter().copied().collect::<Vec<_>>(), vec![4, 3, 2, 1, 0]);
+        assert_eq!(descending.range((Bound::Included(3), Bound::Included(1))).copied().collect::<Vec<_>>(), vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn test_multiset_event_queue() {
+        /* (time, name) events ordered by time only; ties keep insertion order. */
+        let mut queue = RBMultiSet::with_comparator(|a: &(u32, &str), b: &(u32, &str)| a.0.cmp(&b.0));
+        for event in [(5, "tick"), (1, "start"), (5, "tock"), (9, "stop"), (5, "tack")] {
+            queue.insert(event);
+        }
+        assert_eq!(queue.len(), 5);
+        assert_eq!(queue.count(&(5, "")), 3);
+        assert_eq!(queue.equal_range(&(5, "")).map(|e| e.1).collect::<Vec<_>>(), vec!["tick", "tock", "tack"]);
+        assert_eq!(queue.remove_one(&(5, "")), Some((5, "tick")));
+        assert_eq!(queue.remove_all(&(5, "")), 2);
+        assert_eq!(queue.remove_one(&(5, "")), None);
+        assert_eq!(queue.iter().map(|e| e.1).collect::<Vec<_>>(), vec!["start", "stop"]);
+
+        let mut histogram = RBMultiSet::<u8>::new();
+        for byte in b"mississippi" {
+            histogram.insert(*byte);
+        }
+        assert_eq!(histogram.count(&b's'), 4);
+        assert_eq!(histogram.count(&b'z'), 0);
+        assert_eq!(histogram.len(), 11);
+    }
+
+    #[test]
+    fn test_cursor_sweep() {
+        /* Thin out points so that no two kept points are closer than 10. */
+        let mut points = RBTree::<u32>::new();
+        for p in [1, 4, 12, 15, 21, 40, 45, 49, 60] {
+            points.insert(p);
+        }
+        let mut cursor = points.cursor_front_mut();
+        let mut last_kept = *cursor.key().unwrap();
+        cursor.move_next();
+        while let Some(&p) = cursor.key() {
+            if p - last_kept < 10 {
+                cursor.remove_current();
+            } else {
+                last_kept = p;
+                cursor.move_next();
+            }
+        }
+        drop(cursor);
+        assert_eq!(points.iter().copied().collect::<Vec<_>>(), vec![1, 12, 40, 60]);
+
+        let mut cursor = points.lower_bound_mut(Bound::Excluded(&12));
+        assert_eq!(cursor.key(), Some(&40));
+        assert_eq!(cursor.peek_prev(), Some((&12, &())));
+        cursor.insert_before(30, ());
+        cursor.insert_after(50, ());
+        assert_eq!(cursor.peek_next(), Some((&50, &())));
+        drop(cursor);
+        assert_eq!(points.iter().copied().collect::<Vec<_>>(), vec![1, 12, 30, 40, 50, 60]);
+        assert_eq!(points.upper_bound(Bound::Included(&45)).key(), Some(&40));
+        assert!(points.cursor_at(&41).is_none());
+    }
+
+    #[test]
+    fn test_hinted_insert() {
+        /* Readings arrive roughly in time order; each is placed from the last. */
+        let mut readings = RBMap::<u64, f32>::new();
+        let mut cursor = readings.cursor_front_mut();
+        for (time, value) in [(100, 1.0), (105, 1.5), (103, 1.2), (110, 2.0), (108, 1.8), (105, 1.6)] {
+            cursor.insert(time, value);
+        }
+        assert_eq!(cursor.key_value(), Some((&105, &1.6)));
+        assert!(cursor.seek(&110));
+        assert!(!cursor.seek(&104));
+        assert_eq!(cursor.key(), Some(&105));
+        assert!(!cursor.seek(&111));
+        assert_eq!(cursor.key(), None);
+        drop(cursor);
+        assert_eq!(readings.keys().copied().collect::<Vec<_>>(), vec![100, 103, 105, 108, 110]);
+        assert!(readings.validate().is_ok());
+    }
+
+    #[test]
+    fn test_bulk_construction() {
+        let tree = RBTree::from_sorted_iter(0..1000);
+        assert_eq!(tree.len(), 1000);
+        assert_eq!(tree.select(500), Some(&500));
+
+        let mut tree: RBTree<i32> = (0..100).chain(50..150).collect();
+        tree.extend((200..300).rev());
+        assert!(tree.iter().copied().eq((0..150).chain(200..300)));
+
+        let map: RBMap<&str, i32> = [("b", 2), ("a", 1), ("c", 3), ("a", 4)].into_iter().collect();
+        assert_eq!(map.get(&"a"), Some(&4));
+        assert_eq!(map.len(), 3);
+    }
+
+    #[test]
+    #[cfg(feature = "std")]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join(format!("rb_tree_save_and_load_{}.bin", std::process::id()));
+        let mut scores = RBMap::<String, (u32, bool)>::new();
+        for (i, name) in ["carol", "alice", "bob", "dave"].into_iter().enumerate() {
+            scores.insert(name.to_string(), (i as u32 * 10, i % 2 == 0));
+        }
+        scores.write_to(std::fs::File::create(&path).unwrap()).unwrap();
+        let loaded = RBMap::<String, (u32, bool)>::read_from(std::fs::File::open(&path).unwrap()).unwrap();
+        assert!(loaded.iter().eq(scores.iter()));
+
+        /* A file cut short by a crash is an error, not a panic. */
+        let bytes = std::fs::read(&path).unwrap();
+        std::fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+        let truncated = RBMap::<String, (u32, bool)>::read_from(std::fs::File::open(&path).unwrap());
+        assert!(matches!(truncated, Err(rb_tree::DecodeError::Truncated)));
+        std::fs::remove_file(&path).unwrap();
+
+        let tree = RBTree::from_sorted_iter(0..10000u32);
+        assert!(RBTree::<u32>::from_bytes(&tree.to_bytes()).unwrap().iter().eq(tree.iter()));
+    }
+
+    #[test]
+    fn test_render() {
+        let tree = RBTree::from_sorted_iter(1..=3);
+        assert_eq!(tree.text().to_string(), "  ┌- 3 B\n- 2 B\n  └- 1 B\n");
+        assert_eq!(tree.text().with_sizes().to_string(), "  ┌- 3 B [size=1]\n- 2 B [size=3]\n  └- 1 B [size=1]\n");
+
+        let mut dot = String::new();
+        write!(dot, "{}", tree.dot()).unwrap();
+        assert!(dot.starts_with("digraph RBTree {"));
+        assert_eq!(dot.matches("fillcolor=black").count(), 3);
+        assert_eq!(dot.matches(" -> ").count(), 2);
+
+        let mut svg = Vec::new();
+        write!(svg, "{}", RBTree::<String>::from_sorted_iter(["<a>".to_string()]).svg()).unwrap();
+        let svg = String::from_utf8(svg).unwrap();
+        assert!(svg.contains("&quot;&lt;a&gt;&quot;"));
+        assert_eq!(svg.matches("<circle").count(), 1);
+    }
+
+    #[test]
+    fn test_validate() {
+        let mut tree: RBTree<i32> = (0..500).rev().collect();
+        for k in (0..500).step_by(3) {
+            tree.remove(&k);
+        }
+        assert_eq!(tree.validate(), Ok(()));
+
+        let sums: RBTree<i64, Sum> = (0..100).collect();
+        assert!(sums.validate().is_ok());
+
+        let mut events = RBMultiSet::new();
+        events.insert(5);
+        events.insert(5);
+        events.insert(2);
+        if let Err(violation) = events.validate() {
+            panic!("multiset is invalid: {}", violation);
+        }
+    }
+
+    #[test]
+    fn test_set_return_values() {
+        let mut tree = RBTree::new();
+        assert!(tree.insert("b"));
+        assert!(!tree.insert("b"));
+        assert_eq!(tree.checked_insert("a"), Ok(true));
+        assert_eq!(tree.replace("c"), None);
+        assert_eq!(tree.replace("c"), Some("c"));
+        assert!(tree.remove(&"a"));
+        assert!(!tree.remove(&"a"));
+        assert_eq!(tree.take(&"b"), Some("b"));
+        assert_eq!(tree.take(&"b"), None);
+        assert!(tree.iter().eq([&"c"]));
+    }
+
+    #[test]
+    fn test_neighbour_queries() {
+        /* Free meeting slots, as start times; book the first free slot at or
+         * after each request and report the nearest alternatives. */
+        let mut free: RBTree<u32> = (9..17).collect();
+        assert_eq!(free.ceiling(&12), Some(&12));
+        assert!(free.remove(&12));
+        assert_eq!(free.ceiling(&12), Some(&13));
+        assert_eq!(free.floor(&12), Some(&11));
+        assert_eq!(free.lower(&11), Some(&10));
+        assert_eq!(free.higher(&11), Some(&13));
+        assert_eq!(free.higher(&16), None);
+        assert_eq!(free.lower(&9), None);
+        assert_eq!((free.first(), free.last()), (Some(&9), Some(&16)));
+        assert_eq!(free.pop_first(), Some(9));
+        assert_eq!(free.pop_last(), Some(16));
+        assert_eq!(free.len(), 5);
+
+        let mut prices = RBMap::<u32, &str>::new();
+        prices.insert(10, "ten");
+        prices.insert(20, "twenty");
+        assert_eq!(prices.floor(&15), Some((&10, &"ten")));
+        assert_eq!(prices.ceiling(&15), Some((&20, &"twenty")));
+        assert_eq!(prices.first_key_value(), Some((&10, &"ten")));
+        assert_eq!(prices.last_key_value(), Some((&20, &"twenty")));
+        assert_eq!(prices.pop_last(), Some((20, "twenty")));
+        assert_eq!(prices.pop_first(), Some((10, "ten")));
+        assert_eq!(prices.pop_first(), None);
+    }
+
+    #[test]
+    fn test_conditional_removal() {
+        let mut sessions: RBMap<u32, &str> = (0..100).map(|id| (id, if id % 10 == 0 {"expired"} else {"live"})).collect();
+        let expired = sessions.extract_if(|_, state| *state == "expired").map(|(id, _)| id).collect::<Vec<_>>();
+        assert_eq!(expired, vec![0, 10, 20, 30, 40, 50, 60, 70, 80, 90]);
+        assert_eq!(sessions.remove_range(20..40), 18);
+        sessions.retain(|id, _| id % 2 == 1);
+        assert_eq!(sessions.len(), 40);
+        assert!(sessions.validate().is_ok());
+
+        let mut tree: RBTree<i32> = (0..10).collect();
+        assert_eq!(tree.extract_if(|k| k % 2 == 0).next(), Some(0));
+        assert_eq!(tree.len(), 9);
+        assert_eq!(tree.remove_range(..=5), 5);
+        assert!(tree.iter().copied().eq(6..10));
+    }
+
+    /* A call site written once against `OrderedSet`. */
+    fn working_hours<T: OrderedSet<u32> + Default>(requests: &[u32]) -> Vec<u32> {
+        let mut hours = T::default();
+        for &hour in requests {
+            hours.insert(hour);
+        }
+        hours.remove(&3);
+        hours.range(9..18).copied().collect()
+    }
+
+    #[test]
+    fn test_ordered_set() {
+        let requests = [3, 9, 23, 12, 9, 17, 18, 3, 10];
+        let expected = vec![9, 10, 12, 17];
+        assert_eq!(working_hours::<RBTree<u32>>(&requests), expected);
+        assert_eq!(working_hours::<AvlTree<u32>>(&requests), expected);
+        assert_eq!(working_hours::<Treap<u32>>(&requests), expected);
+        assert_eq!(working_hours::<BTreeSet<u32>>(&requests), expected);
+    }
+}